@@ -1,26 +1,43 @@
 use cosmwasm_std::{
-    attr, entry_point, from_binary, to_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo,
-    Response, StdError, StdResult, SubMsg, Uint128, WasmMsg,
+    attr, coins, entry_point, from_json, to_json_binary, Addr, Api, BankMsg, Binary, BlockInfo,
+    CosmosMsg, Decimal, Deps, DepsMut, Env, MessageInfo, Order, Response, StdError, StdResult,
+    Storage, SubMsg, Timestamp, Uint128, WasmMsg,
 };
 
 use crate::state::{
-    read_vesting_infos, Config, OwnershipProposal, CONFIG, OWNERSHIP_PROPOSAL, VESTING_INFO,
+    nfts, read_nft_ids, read_outstanding_amount, read_programs, read_tracked_outstanding_amount,
+    read_vesting_infos, Config, Nft, OwnershipProposal, Program, Totals, ALLOWED_TOKENS, CLAIMERS,
+    CONFIG, DEFAULT_PROGRAM_ID, DEFAULT_RECIPIENTS, NFT_COUNT, NFT_IDS, OWNERSHIP_PROPOSAL,
+    PENDING_TRANSFERS, PROGRAMS, PROGRAM_COUNT, RELEASED_AMOUNTS, TOTALS, TOTAL_UNRELEASED_AMOUNTS,
+    UNRELEASED_AMOUNTS, VESTING_INFO,
 };
 
 use crate::error::ContractError;
 
+use crate::migration::{migrate_config, migrate_vesting_infos};
 use crate::msg::{
-    ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg, MigrateMsg, OrderBy, QueryMsg,
+    AddrUpdate, AllNftInfoResponse, ClaimerInfo, ConfigResponse, ContractInfoResponse, Cw20HookMsg,
+    Cw721ReceiveMsg, ExecuteMsg, InstantiateMsg, InvariantsResponse, MigrateMsg, NftInfoResponse,
+    NumTokensResponse, OrderBy, OwnerOfResponse, ProgramResponse, ProgramsResponse, QueryMsg,
+    TimeUnit, Token, TokensResponse, TotalPowerAtHeightResponse, TotalsResponse, TransferPolicy,
     VestingAccount, VestingAccountResponse, VestingAccountsResponse, VestingInfo, VestingSchedule,
+    VestingSchedulePoint, VotingPowerAtHeightResponse,
+};
+use crate::util::{
+    addr_opt_validate, addr_update, addr_validate_to_lower, token_opt_validate, token_validate,
 };
-use crate::util::{addr_opt_validate, addr_validate_to_lower};
 use cw2::set_contract_version;
-use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
-use cw_storage_plus::Item;
+use cw20::{BalanceResponse as Cw20BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg, Cw20ReceiveMsg};
+use cw_storage_plus::{Item, SnapshotMap};
+use cw_utils::one_coin;
+use std::collections::HashMap;
+use std::iter;
 
 const CONTRACT_NAME: &str = "clawbackable-vesting";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 const MAX_PROPOSAL_TTL: u64 = 1209600;
+const NFT_NAME: &str = "Vesting Positions";
+const NFT_SYMBOL: &str = "VEST";
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -31,13 +48,28 @@ pub fn instantiate(
 ) -> StdResult<Response> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
+    let owner = addr_validate_to_lower(deps.api, &msg.owner)?;
+
     CONFIG.save(
         deps.storage,
         &Config {
-            owner: addr_validate_to_lower(deps.api, &msg.owner)?,
-            token_addr: addr_validate_to_lower(deps.api, &msg.token_addr)?,
+            owner: owner.clone(),
+        },
+    )?;
+
+    PROGRAMS.save(
+        deps.storage,
+        DEFAULT_PROGRAM_ID,
+        &Program {
+            owner,
+            token: token_validate(deps.api, msg.token)?,
+            clawback_recipient: addr_opt_validate(deps.api, &msg.clawback_recipient)?,
+            clawbackable: true,
+            transfer_policy: TransferPolicy::Beneficiary,
+            operator: None,
         },
     )?;
+    PROGRAM_COUNT.save(deps.storage, &(DEFAULT_PROGRAM_ID + 1))?;
 
     Ok(Response::new())
 }
@@ -50,9 +82,40 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Claim { recipient, amount } => claim(deps, env, info, recipient, amount),
-        ExecuteMsg::Clawback { recipient } => clawback(deps, env, info, recipient),
-        ExecuteMsg::Receive(msg) => receive_cw20(deps, info, msg),
+        ExecuteMsg::Claim {
+            recipient,
+            splits,
+            amount,
+            token,
+            program_id,
+            msg,
+        } => {
+            let recipients = claim_recipients(deps.api, recipient, splits)?;
+            claim(deps, env, info, recipients, amount, token, program_id, msg)
+        }
+        ExecuteMsg::Clawback {
+            recipient,
+            amount,
+            schedule_ids,
+            clawback_recipient,
+            token,
+            program_id,
+        } => clawback(
+            deps,
+            env,
+            info,
+            recipient,
+            amount,
+            schedule_ids,
+            clawback_recipient,
+            token,
+            program_id,
+        ),
+        ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
+        ExecuteMsg::RegisterVestingAccounts {
+            vesting_accounts,
+            program_id,
+        } => receive_native(deps, env, info, vesting_accounts, program_id),
         ExecuteMsg::ProposeNewOwner { owner, expires_in } => {
             propose_new_owner(deps, info, env, owner, expires_in, OWNERSHIP_PROPOSAL)
         }
@@ -60,26 +123,184 @@ pub fn execute(
             drop_ownership_proposal(deps, info, OWNERSHIP_PROPOSAL)
         }
         ExecuteMsg::ClaimOwnership {} => claim_ownership(deps, info, env, OWNERSHIP_PROPOSAL),
+        ExecuteMsg::CreateProgram {
+            owner,
+            token,
+            clawback_recipient,
+            clawbackable,
+            transfer_policy,
+            operator,
+        } => create_program(
+            deps,
+            info,
+            owner,
+            token,
+            clawback_recipient,
+            clawbackable,
+            transfer_policy,
+            operator,
+        ),
+        ExecuteMsg::UpdateProgram {
+            program_id,
+            owner,
+            clawback_recipient,
+            clawbackable,
+            transfer_policy,
+            operator,
+        } => update_program(
+            deps,
+            info,
+            program_id,
+            owner,
+            clawback_recipient,
+            clawbackable,
+            transfer_policy,
+            operator,
+        ),
+        ExecuteMsg::AllowToken { token, program_id } => allow_token(deps, info, token, program_id),
+        ExecuteMsg::DisallowToken { token, program_id } => {
+            disallow_token(deps, info, token, program_id)
+        }
+        ExecuteMsg::TransferVestingAccount {
+            new_address,
+            token,
+            program_id,
+        } => transfer_vesting_account(deps, env, info, new_address, token, program_id),
+        ExecuteMsg::ApproveVestingAccountTransfer {
+            address,
+            token,
+            program_id,
+        } => approve_vesting_account_transfer(deps, env, info, address, token, program_id),
+        ExecuteMsg::GrantClaimer {
+            claimer,
+            expires,
+            max_amount,
+            recipient,
+            token,
+            program_id,
+        } => {
+            let claimer_info = ClaimerInfo {
+                expires: expires.unwrap_or_default(),
+                max_amount,
+                recipient: addr_opt_validate(deps.api, &recipient)?,
+            };
+            grant_claimer(deps, info, claimer, claimer_info, token, program_id)
+        }
+        ExecuteMsg::RevokeClaimer {
+            claimer,
+            token,
+            program_id,
+        } => revoke_claimer(deps, info, claimer, token, program_id),
+        ExecuteMsg::ClaimFor {
+            beneficiary,
+            amount,
+            token,
+            program_id,
+        } => claim_for(deps, env, info, beneficiary, amount, token, program_id),
+        ExecuteMsg::Distribute {
+            addresses,
+            token,
+            program_id,
+        } => {
+            let addresses = addresses
+                .iter()
+                .map(|address| addr_validate_to_lower(deps.api, address))
+                .collect::<StdResult<Vec<_>>>()?;
+            distribute(deps, env, info, addresses, token, program_id)
+        }
+        ExecuteMsg::DistributeAll {
+            start_after,
+            limit,
+            token,
+            program_id,
+        } => distribute_all(deps, env, info, start_after, limit, token, program_id),
+        ExecuteMsg::SetDefaultRecipient {
+            recipient,
+            token,
+            program_id,
+        } => set_default_recipient(deps, info, recipient, token, program_id),
+        ExecuteMsg::WithdrawSurplus {
+            recipient,
+            amount,
+            token,
+        } => withdraw_surplus(deps, env, info, recipient, amount, token),
+        ExecuteMsg::TransferNft {
+            recipient,
+            token_id,
+        } => transfer_nft(deps, env, info, recipient, token_id),
+        ExecuteMsg::SendNft {
+            contract,
+            token_id,
+            msg,
+        } => send_nft(deps, env, info, contract, token_id, msg),
     }
 }
 
 fn receive_cw20(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     cw20_msg: Cw20ReceiveMsg,
 ) -> Result<Response, ContractError> {
-    let config = CONFIG.load(deps.storage)?;
+    match from_json(&cw20_msg.msg)? {
+        Cw20HookMsg::RegisterVestingAccounts {
+            vesting_accounts,
+            program_id,
+        } => {
+            let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+            let program = PROGRAMS.load(deps.storage, program_id)?;
+
+            // Permission check
+            if cw20_msg.sender != program.owner {
+                return Err(ContractError::Unauthorized {});
+            }
+
+            // Any contract can call the hook and pose as the program owner, so only the tokens
+            // of the program are trusted
+            let token = Token::Cw20(info.sender);
+            assert_allowed_token(deps.storage, program_id, &program, &token)?;
+
+            register_vesting_accounts(
+                deps,
+                env,
+                program_id,
+                &program,
+                token,
+                vesting_accounts,
+                cw20_msg.amount,
+            )
+        }
+    }
+}
+
+fn receive_native(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    vesting_accounts: Vec<VestingAccount>,
+    program_id: Option<u64>,
+) -> Result<Response, ContractError> {
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    let program = PROGRAMS.load(deps.storage, program_id)?;
 
     // Permission check
-    if cw20_msg.sender != config.owner || info.sender != config.token_addr {
+    if info.sender != program.owner {
         return Err(ContractError::Unauthorized {});
     }
 
-    match from_binary(&cw20_msg.msg)? {
-        Cw20HookMsg::RegisterVestingAccounts { vesting_accounts } => {
-            register_vesting_accounts(deps, vesting_accounts, cw20_msg.amount)
-        }
-    }
+    let coin = one_coin(&info)?;
+    let token = Token::Native(coin.denom);
+    assert_allowed_token(deps.storage, program_id, &program, &token)?;
+
+    register_vesting_accounts(
+        deps,
+        env,
+        program_id,
+        &program,
+        token,
+        vesting_accounts,
+        coin.amount,
+    )
 }
 
 pub fn propose_new_owner(
@@ -167,9 +388,21 @@ pub fn claim_ownership(
 
     proposal.remove(deps.storage);
 
-    CONFIG.update::<_, StdError>(deps.storage, |mut v| {
-        v.owner = p.owner.clone();
-        Ok(v)
+    let config = CONFIG.load(deps.storage)?;
+    CONFIG.save(
+        deps.storage,
+        &Config {
+            owner: p.owner.clone(),
+        },
+    )?;
+
+    // The default program follows the contract ownership, unless it was handed to someone else
+    PROGRAMS.update::<_, StdError>(deps.storage, DEFAULT_PROGRAM_ID, |program| {
+        let mut program = program.ok_or_else(|| StdError::not_found("Program"))?;
+        if program.owner == config.owner {
+            program.owner = p.owner.clone();
+        }
+        Ok(program)
     })?;
 
     Ok(Response::new().add_attributes(vec![
@@ -178,285 +411,2071 @@ pub fn claim_ownership(
     ]))
 }
 
-pub fn register_vesting_accounts(
+#[allow(clippy::too_many_arguments)]
+pub fn create_program(
     deps: DepsMut,
-    vesting_accounts: Vec<VestingAccount>,
-    cw20_amount: Uint128,
+    info: MessageInfo,
+    owner: String,
+    token: Token,
+    clawback_recipient: Option<String>,
+    clawbackable: Option<bool>,
+    transfer_policy: Option<TransferPolicy>,
+    operator: Option<String>,
 ) -> Result<Response, ContractError> {
-    let response = Response::new();
+    let config = CONFIG.load(deps.storage)?;
 
-    let mut to_deposit = Uint128::zero();
+    // Permission check
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
 
-    for mut vesting_account in vesting_accounts {
-        let mut released_amount = Uint128::zero();
-        let account_address = addr_validate_to_lower(deps.api, &vesting_account.address)?;
+    let program_id = PROGRAM_COUNT.load(deps.storage)?;
+    let program = Program {
+        owner: addr_validate_to_lower(deps.api, owner)?,
+        token: token_validate(deps.api, token)?,
+        clawback_recipient: addr_opt_validate(deps.api, &clawback_recipient)?,
+        clawbackable: clawbackable.unwrap_or(true),
+        transfer_policy: transfer_policy.unwrap_or(TransferPolicy::Beneficiary),
+        operator: addr_opt_validate(deps.api, &operator)?,
+    };
 
-        assert_vesting_schedules(&account_address, &vesting_account.schedules)?;
+    PROGRAMS.save(deps.storage, program_id, &program)?;
+    PROGRAM_COUNT.save(deps.storage, &(program_id + 1))?;
 
-        for sch in &vesting_account.schedules {
-            let amount = if let Some(end_point) = &sch.end_point {
-                end_point.amount
-            } else {
-                sch.start_point.amount
-            };
-            to_deposit = to_deposit.checked_add(amount)?;
-        }
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "create_program"),
+        attr("program_id", program_id.to_string()),
+        attr("owner", program.owner),
+        attr("token", program.token.key()),
+    ]))
+}
 
-        if let Some(mut old_info) = VESTING_INFO.may_load(deps.storage, &account_address)? {
-            released_amount = old_info.released_amount;
-            vesting_account.schedules.append(&mut old_info.schedules);
-        }
+#[allow(clippy::too_many_arguments)]
+pub fn update_program(
+    deps: DepsMut,
+    info: MessageInfo,
+    program_id: Option<u64>,
+    owner: Option<String>,
+    clawback_recipient: Option<AddrUpdate>,
+    clawbackable: Option<bool>,
+    transfer_policy: Option<TransferPolicy>,
+    operator: Option<AddrUpdate>,
+) -> Result<Response, ContractError> {
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    let mut program = PROGRAMS.load(deps.storage, program_id)?;
 
-        VESTING_INFO.save(
-            deps.storage,
-            &account_address,
-            &VestingInfo {
-                schedules: vesting_account.schedules,
-                released_amount,
-                clawbackable: vesting_account.clawbackable,
-            },
-        )?;
+    // Permission check
+    if info.sender != program.owner {
+        return Err(ContractError::Unauthorized {});
     }
 
-    if to_deposit != cw20_amount {
-        return Err(ContractError::VestingScheduleAmountError {});
+    let mut attributes = vec![
+        attr("action", "update_program"),
+        attr("program_id", program_id.to_string()),
+    ];
+
+    if let Some(owner) = owner {
+        program.owner = addr_validate_to_lower(deps.api, owner)?;
+        attributes.push(attr("owner", &program.owner));
     }
 
-    Ok(response.add_attributes({
-        vec![
-            attr("action", "register_vesting_accounts"),
-            attr("deposited", to_deposit),
-        ]
-    }))
+    if let Some(clawback_recipient) = clawback_recipient {
+        program.clawback_recipient = addr_update(deps.api, clawback_recipient)?;
+        attributes.push(attr(
+            "clawback_recipient",
+            addr_attr_value(&program.clawback_recipient),
+        ));
+    }
+
+    if let Some(clawbackable) = clawbackable {
+        program.clawbackable = clawbackable;
+        attributes.push(attr("clawbackable", clawbackable.to_string()));
+    }
+
+    if let Some(transfer_policy) = transfer_policy {
+        attributes.push(attr("transfer_policy", format!("{:?}", transfer_policy)));
+        program.transfer_policy = transfer_policy;
+    }
+
+    if let Some(operator) = operator {
+        program.operator = addr_update(deps.api, operator)?;
+        attributes.push(attr("operator", addr_attr_value(&program.operator)));
+    }
+
+    PROGRAMS.save(deps.storage, program_id, &program)?;
+
+    Ok(Response::new().add_attributes(attributes))
 }
 
-fn assert_vesting_schedules(
-    addr: &Addr,
-    vesting_schedules: &[VestingSchedule],
+fn addr_attr_value(addr: &Option<Addr>) -> String {
+    addr.as_ref()
+        .map_or_else(|| "none".to_string(), Addr::to_string)
+}
+
+/// Fails unless the token is the default token of the program or one it allows.
+fn assert_allowed_token(
+    storage: &dyn Storage,
+    program_id: u64,
+    program: &Program,
+    token: &Token,
 ) -> Result<(), ContractError> {
-    for sch in vesting_schedules {
-        if let Some(end_point) = &sch.end_point {
-            if !(sch.start_point.time < end_point.time && sch.start_point.amount < end_point.amount)
-            {
-                return Err(ContractError::VestingScheduleError(addr.to_string()));
-            }
-        }
+    if *token != program.token && !ALLOWED_TOKENS.has(storage, (program_id, token.key())) {
+        return Err(ContractError::InvalidToken {});
     }
 
     Ok(())
 }
 
-pub fn claim(
+pub fn allow_token(
     deps: DepsMut,
-    env: Env,
     info: MessageInfo,
-    recipient: Option<String>,
-    amount: Option<Uint128>,
+    token: Token,
+    program_id: Option<u64>,
 ) -> Result<Response, ContractError> {
-    let config = CONFIG.load(deps.storage)?;
-    let mut vesting_info = VESTING_INFO.load(deps.storage, &info.sender)?;
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    let program = PROGRAMS.load(deps.storage, program_id)?;
 
-    let available_amount = compute_available_amount(env.block.time.seconds(), &vesting_info)?;
+    // Permission check
+    if info.sender != program.owner {
+        return Err(ContractError::Unauthorized {});
+    }
 
-    let claim_amount = if let Some(a) = amount {
-        if a > available_amount {
-            return Err(ContractError::AmountIsNotAvailable {});
-        };
-        a
-    } else {
-        available_amount
-    };
+    let token = token_validate(deps.api, token)?;
+    ALLOWED_TOKENS.save(deps.storage, (program_id, token.key()), &token)?;
 
-    let mut response = Response::new();
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "allow_token"),
+        attr("program_id", program_id.to_string()),
+        attr("token", token.key()),
+    ]))
+}
 
-    if !claim_amount.is_zero() {
-        response = response.add_submessage(SubMsg::new(WasmMsg::Execute {
-            contract_addr: config.token_addr.to_string(),
-            funds: vec![],
-            msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                recipient: recipient.unwrap_or_else(|| info.sender.to_string()),
-                amount: claim_amount,
-            })?,
-        }));
+pub fn disallow_token(
+    deps: DepsMut,
+    info: MessageInfo,
+    token: Token,
+    program_id: Option<u64>,
+) -> Result<Response, ContractError> {
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    let program = PROGRAMS.load(deps.storage, program_id)?;
 
-        vesting_info.released_amount = vesting_info.released_amount.checked_add(claim_amount)?;
-        VESTING_INFO.save(deps.storage, &info.sender, &vesting_info)?;
-    };
+    // Permission check
+    if info.sender != program.owner {
+        return Err(ContractError::Unauthorized {});
+    }
 
-    Ok(response.add_attributes(vec![
-        attr("action", "claim"),
-        attr("address", &info.sender),
-        attr("available_amount", available_amount),
-        attr("claimed_amount", claim_amount),
+    let token = token_validate(deps.api, token)?;
+    ALLOWED_TOKENS.remove(deps.storage, (program_id, token.key()));
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "disallow_token"),
+        attr("program_id", program_id.to_string()),
+        attr("token", token.key()),
     ]))
 }
 
-fn compute_available_amount(current_time: u64, vesting_info: &VestingInfo) -> StdResult<Uint128> {
-    let mut available_amount: Uint128 = Uint128::zero();
-    for sch in &vesting_info.schedules {
-        if sch.start_point.time > current_time {
-            continue;
-        }
+pub fn transfer_vesting_account(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    new_address: String,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> Result<Response, ContractError> {
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    let program = PROGRAMS.load(deps.storage, program_id)?;
+    let token = token_opt_validate(deps.api, token)?.unwrap_or(program.token);
+    let new_address = addr_validate_to_lower(deps.api, new_address)?;
 
-        available_amount = available_amount.checked_add(sch.start_point.amount)?;
+    if new_address == info.sender {
+        return Err(ContractError::Std(StdError::generic_err(
+            "New address cannot be same",
+        )));
+    }
 
-        if let Some(end_point) = &sch.end_point {
-            let passed_time = current_time.min(end_point.time) - sch.start_point.time;
-            let time_period = end_point.time - sch.start_point.time;
-            if passed_time != 0 && time_period != 0 {
-                let release_amount = Uint128::from(passed_time).multiply_ratio(
-                    end_point.amount.checked_sub(sch.start_point.amount)?,
-                    time_period,
-                );
-                available_amount = available_amount.checked_add(release_amount)?;
-            }
-        }
+    // Only the beneficiary can move its account
+    if !VESTING_INFO.has(deps.storage, (program_id, token.key(), &info.sender)) {
+        return Err(ContractError::Unauthorized {});
     }
 
-    available_amount
-        .checked_sub(vesting_info.released_amount)
-        .map_err(StdError::from)
+    let attributes = vec![
+        attr("address", &info.sender),
+        attr("new_address", &new_address),
+        attr("program_id", program_id.to_string()),
+        attr("token", token.key()),
+    ];
+
+    match program.transfer_policy {
+        TransferPolicy::Beneficiary => {
+            move_vesting_account(
+                deps.storage,
+                env.block.height,
+                (program_id, token.key()),
+                &info.sender,
+                &new_address,
+            )?;
+
+            Ok(Response::new()
+                .add_attribute("action", "transfer_vesting_account")
+                .add_attributes(attributes))
+        }
+        TransferPolicy::OwnerApproval => {
+            PENDING_TRANSFERS.save(
+                deps.storage,
+                (program_id, token.key(), &info.sender),
+                &new_address,
+            )?;
+
+            Ok(Response::new()
+                .add_attribute("action", "request_vesting_account_transfer")
+                .add_attributes(attributes))
+        }
+    }
 }
 
-pub fn clawback(
+pub fn approve_vesting_account_transfer(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    recipient: Addr,
+    address: String,
+    token: Option<Token>,
+    program_id: Option<u64>,
 ) -> Result<Response, ContractError> {
-    let config = CONFIG.load(deps.storage)?;
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    let program = PROGRAMS.load(deps.storage, program_id)?;
+    let token = token_opt_validate(deps.api, token)?.unwrap_or(program.token);
+    let address = addr_validate_to_lower(deps.api, address)?;
 
     // Permission check
-    if info.sender != config.owner {
+    if info.sender != program.owner {
         return Err(ContractError::Unauthorized {});
     }
 
-    let mut vesting_info = VESTING_INFO.load(deps.storage, &recipient)?;
-
-    if let Some(clawbackable) = vesting_info.clawbackable {
-        if !clawbackable {
-            return Err(ContractError::Unauthorized {});
-        }
-    };
-
-    let claim_amount = compute_available_clawback_amount(env.block.time.seconds(), &vesting_info)?;
-
-    let mut response = Response::new();
-
-    if !claim_amount.is_zero() {
-        response = response.add_submessage(SubMsg::new(WasmMsg::Execute {
-            contract_addr: config.token_addr.to_string(),
-            funds: vec![],
-            msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                recipient: info.sender.to_string(),
-                amount: claim_amount,
-            })?,
-        }));
+    let key = (program_id, token.key(), &address);
+    let new_address = PENDING_TRANSFERS
+        .may_load(deps.storage, key)?
+        .ok_or_else(|| ContractError::Std(StdError::generic_err("Transfer request not found")))?;
+    PENDING_TRANSFERS.remove(deps.storage, key);
 
-        vesting_info.released_amount = vesting_info.released_amount.checked_add(claim_amount)?;
-        VESTING_INFO.save(deps.storage, &info.sender, &vesting_info)?;
-    };
+    move_vesting_account(
+        deps.storage,
+        env.block.height,
+        (program_id, token.key()),
+        &address,
+        &new_address,
+    )?;
 
-    Ok(response.add_attributes(vec![
-        attr("action", "claim"),
-        attr("address", &info.sender),
-        attr("claimed_amount", claim_amount),
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "transfer_vesting_account"),
+        attr("address", &address),
+        attr("new_address", &new_address),
+        attr("program_id", program_id.to_string()),
+        attr("token", token.key()),
     ]))
 }
 
-fn compute_available_clawback_amount(
-    current_time: u64,
-    vesting_info: &VestingInfo,
-) -> StdResult<Uint128> {
-    let mut available_amount: Uint128 = Uint128::zero();
-    for sch in &vesting_info.schedules {
-        if sch.start_point.time > current_time {
-            continue;
+/// Moves a vesting account to a new address. An account already at the new address keeps its
+/// schedules first, so their ids do not change, and the released amounts add up.
+fn move_vesting_account(
+    storage: &mut dyn Storage,
+    height: u64,
+    (program_id, token_key): (u64, &str),
+    address: &Addr,
+    new_address: &Addr,
+) -> StdResult<()> {
+    let mut vesting_info = VESTING_INFO.load(storage, (program_id, token_key, address))?;
+    let nft_id = NFT_IDS.may_load(storage, (program_id, token_key, address))?;
+
+    if let Some(mut new_info) =
+        VESTING_INFO.may_load(storage, (program_id, token_key, new_address))?
+    {
+        // Each token must keep representing a single vesting account
+        if nft_id.is_some() || NFT_IDS.has(storage, (program_id, token_key, new_address)) {
+            return Err(StdError::generic_err(
+                "Vesting accounts represented by an NFT cannot be merged",
+            ));
         }
 
-        if let Some(end_point) = &sch.end_point {
-            available_amount = available_amount.checked_add(end_point.amount)?;
-        } else {
-            available_amount = available_amount.checked_add(sch.start_point.amount)?;
-        }
+        new_info.schedules.append(&mut vesting_info.schedules);
+        new_info.released_amount = new_info
+            .released_amount
+            .checked_add(vesting_info.released_amount)?;
+        vesting_info = new_info;
     }
 
-    available_amount
-        .checked_sub(vesting_info.released_amount)
-        .map_err(StdError::from)
-}
+    remove_vesting_info(storage, height, (program_id, token_key, address))?;
+    PENDING_TRANSFERS.remove(storage, (program_id, token_key, address));
+
+    // The claimers and the default recipient were chosen by the previous beneficiary
+    DEFAULT_RECIPIENTS.remove(storage, (program_id, token_key, address));
+    let claimers = CLAIMERS
+        .prefix(((program_id, token_key), address))
+        .keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for claimer in claimers {
+        CLAIMERS.remove(storage, ((program_id, token_key), address, &claimer));
+    }
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::Config {} => Ok(to_binary(&query_config(deps)?)?),
-        QueryMsg::VestingAccount { address } => {
-            Ok(to_binary(&query_vesting_account(deps, address)?)?)
-        }
-        QueryMsg::VestingAccounts {
-            start_after,
-            limit,
-            order_by,
-        } => Ok(to_binary(&query_vesting_accounts(
-            deps,
-            start_after,
-            limit,
-            order_by,
-        )?)?),
-        QueryMsg::AvailableAmount { address } => Ok(to_binary(&query_vesting_available_amount(
-            deps, env, address,
-        )?)?),
-        QueryMsg::Timestamp {} => Ok(to_binary(&query_timestamp(env)?)?),
+    if let Some(nft_id) = nft_id {
+        NFT_IDS.remove(storage, (program_id, token_key, address));
+        NFT_IDS.save(storage, (program_id, token_key, new_address), &nft_id)?;
+        nfts().update(storage, &nft_id, |nft| -> StdResult<_> {
+            let mut nft = nft.ok_or_else(|| StdError::not_found("Nft"))?;
+            nft.owner = new_address.clone();
+            Ok(nft)
+        })?;
     }
+
+    save_vesting_info(
+        storage,
+        height,
+        (program_id, token_key, new_address),
+        &vesting_info,
+    )
 }
 
-pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
-    let config = CONFIG.load(deps.storage)?;
+/// Saves a vesting info and snapshots its amounts.
+fn save_vesting_info(
+    storage: &mut dyn Storage,
+    height: u64,
+    key: (u64, &str, &Addr),
+    vesting_info: &VestingInfo,
+) -> StdResult<()> {
+    VESTING_INFO.save(storage, key, vesting_info)?;
+    snapshot_vesting_amounts(storage, height, key, Some(vesting_info))
+}
 
-    Ok(ConfigResponse {
-        owner: config.owner,
-        token_addr: config.token_addr,
-    })
+fn remove_vesting_info(
+    storage: &mut dyn Storage,
+    height: u64,
+    key: (u64, &str, &Addr),
+) -> StdResult<()> {
+    VESTING_INFO.remove(storage, key);
+    snapshot_vesting_amounts(storage, height, key, None)
 }
 
-pub fn query_timestamp(env: Env) -> StdResult<u64> {
-    Ok(env.block.time.seconds())
+/// Adds `amounts` to the running totals of a program and token.
+fn add_to_totals(
+    storage: &mut dyn Storage,
+    (program_id, token_key): (u64, &str),
+    amounts: &Totals,
+) -> StdResult<()> {
+    TOTALS.update(storage, (token_key, program_id), |totals| -> StdResult<_> {
+        let totals = totals.unwrap_or_default();
+        Ok(Totals {
+            deposited: totals.deposited.checked_add(amounts.deposited)?,
+            released: totals.released.checked_add(amounts.released)?,
+            clawed_back: totals.clawed_back.checked_add(amounts.clawed_back)?,
+        })
+    })?;
+
+    Ok(())
 }
 
-pub fn query_vesting_account(deps: Deps, address: String) -> StdResult<VestingAccountResponse> {
-    let address = addr_validate_to_lower(deps.api, &address)?;
-    let info = VESTING_INFO.load(deps.storage, &address)?;
+/// Snapshots the unreleased and released amounts of a vesting account, which are zero once it is
+/// removed, along with the total unreleased amount of its program and token.
+fn snapshot_vesting_amounts(
+    storage: &mut dyn Storage,
+    height: u64,
+    key: (u64, &str, &Addr),
+    vesting_info: Option<&VestingInfo>,
+) -> StdResult<()> {
+    let (program_id, token_key, _) = key;
+    let (unreleased_amount, released_amount) = match vesting_info {
+        Some(vesting_info) => (
+            compute_unreleased_amount(vesting_info)?,
+            vesting_info.released_amount,
+        ),
+        None => (Uint128::zero(), Uint128::zero()),
+    };
+
+    if RELEASED_AMOUNTS.may_load(storage, key)?.unwrap_or_default() != released_amount {
+        RELEASED_AMOUNTS.save(storage, key, &released_amount, height)?;
+    }
+
+    let previous_amount = UNRELEASED_AMOUNTS
+        .may_load(storage, key)?
+        .unwrap_or_default();
+    if previous_amount == unreleased_amount {
+        return Ok(());
+    }
+
+    UNRELEASED_AMOUNTS.save(storage, key, &unreleased_amount, height)?;
+    TOTAL_UNRELEASED_AMOUNTS.update(
+        storage,
+        (program_id, token_key),
+        height,
+        |total| -> StdResult<_> {
+            Ok(total
+                .unwrap_or_default()
+                .checked_add(unreleased_amount)?
+                .checked_sub(previous_amount)?)
+        },
+    )?;
 
-    Ok(VestingAccountResponse { address, info })
+    Ok(())
 }
 
-pub fn query_vesting_accounts(
-    deps: Deps,
-    start_after: Option<String>,
-    limit: Option<u32>,
-    order_by: Option<OrderBy>,
-) -> StdResult<VestingAccountsResponse> {
-    let start_after = addr_opt_validate(deps.api, &start_after)?;
+pub fn withdraw_surplus(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipient: String,
+    amount: Uint128,
+    token: Option<Token>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
 
-    let vesting_infos = read_vesting_infos(deps, start_after, limit, order_by)?;
+    // Permission check
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
 
-    let vesting_accounts: Vec<_> = vesting_infos
-        .into_iter()
-        .map(|(address, info)| VestingAccountResponse { address, info })
-        .collect();
+    let recipient = addr_validate_to_lower(deps.api, recipient)?;
 
-    Ok(VestingAccountsResponse { vesting_accounts })
+    if amount.is_zero() {
+        return Err(ContractError::ZeroAmount {});
+    }
+
+    // Only the balance above the outstanding amount of all programs can be withdrawn. When the
+    // totals disagree with the accounts, the larger amount is kept.
+    let invariants = query_invariants(deps.as_ref(), env, token)?;
+    let outstanding = invariants.outstanding.max(invariants.tracked_outstanding);
+    if amount > invariants.balance.saturating_sub(outstanding) {
+        return Err(ContractError::AmountIsNotAvailable {});
+    }
+
+    Ok(Response::new()
+        .add_submessage(SubMsg::new(transfer_msg(
+            &invariants.token,
+            &recipient,
+            amount,
+            None,
+        )?))
+        .add_attributes(vec![
+            attr("action", "withdraw_surplus"),
+            attr("recipient", &recipient),
+            attr("token", invariants.token.key()),
+            attr("amount", amount),
+        ]))
 }
 
-pub fn query_vesting_available_amount(deps: Deps, env: Env, address: String) -> StdResult<Uint128> {
-    let address = addr_validate_to_lower(deps.api, &address)?;
+pub fn transfer_nft(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipient: String,
+    token_id: String,
+) -> Result<Response, ContractError> {
+    let recipient = addr_validate_to_lower(deps.api, recipient)?;
+    move_nft(deps, &env, &info.sender, &recipient, &token_id)?;
 
-    let info = VESTING_INFO.load(deps.storage, &address)?;
-    let available_amount = compute_available_amount(env.block.time.seconds(), &info)?;
-    Ok(available_amount)
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "transfer_nft"),
+        attr("sender", &info.sender),
+        attr("recipient", &recipient),
+        attr("token_id", token_id),
+    ]))
 }
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> StdResult<Response> {
+pub fn send_nft(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    contract: String,
+    token_id: String,
+    msg: Binary,
+) -> Result<Response, ContractError> {
+    let contract = addr_validate_to_lower(deps.api, contract)?;
+    move_nft(deps, &env, &info.sender, &contract, &token_id)?;
+
+    let receive_msg = Cw721ReceiveMsg {
+        sender: info.sender.to_string(),
+        token_id: token_id.clone(),
+        msg,
+    };
+
+    Ok(Response::new()
+        .add_message(receive_msg.into_cosmos_msg(&contract)?)
+        .add_attributes(vec![
+            attr("action", "send_nft"),
+            attr("sender", &info.sender),
+            attr("recipient", &contract),
+            attr("token_id", token_id),
+        ]))
+}
+
+/// Moves the vesting account represented by a token to the new owner of the token. The transfer
+/// policy of the program applies, so programs requiring the owner approval have no transferable
+/// tokens.
+fn move_nft(
+    deps: DepsMut,
+    env: &Env,
+    sender: &Addr,
+    recipient: &Addr,
+    token_id: &str,
+) -> Result<(), ContractError> {
+    let nft = nfts().load(deps.storage, token_id)?;
+    let program = PROGRAMS.load(deps.storage, nft.program_id)?;
+
+    // Permission check
+    if *sender != nft.owner || program.transfer_policy != TransferPolicy::Beneficiary {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    move_vesting_account(
+        deps.storage,
+        env.block.height,
+        (nft.program_id, nft.token.key()),
+        &nft.owner,
+        recipient,
+    )?;
+
+    Ok(())
+}
+
+/// Mints a cw721 token representing a vesting account, unless it has one already.
+fn mint_nft(
+    storage: &mut dyn Storage,
+    program_id: u64,
+    token: &Token,
+    owner: &Addr,
+) -> StdResult<Option<String>> {
+    if NFT_IDS.has(storage, (program_id, token.key(), owner)) {
+        return Ok(None);
+    }
+
+    let nft_count = NFT_COUNT.may_load(storage)?.unwrap_or_default();
+    let token_id = nft_count.to_string();
+
+    nfts().save(
+        storage,
+        &token_id,
+        &Nft {
+            owner: owner.clone(),
+            program_id,
+            token: token.clone(),
+        },
+    )?;
+    NFT_IDS.save(storage, (program_id, token.key(), owner), &token_id)?;
+    NFT_COUNT.save(storage, &(nft_count + 1))?;
+
+    Ok(Some(token_id))
+}
+
+pub fn register_vesting_accounts(
+    deps: DepsMut,
+    env: Env,
+    program_id: u64,
+    program: &Program,
+    token: Token,
+    vesting_accounts: Vec<VestingAccount>,
+    deposit_amount: Uint128,
+) -> Result<Response, ContractError> {
+    let mut response = Response::new();
+
+    let mut to_deposit = Uint128::zero();
+
+    for mut vesting_account in vesting_accounts {
+        let mut released_amount = Uint128::zero();
+        let account_address = addr_validate_to_lower(deps.api, &vesting_account.address)?;
+
+        assert_vesting_schedules(&account_address, &vesting_account.schedules)?;
+
+        for sch in &mut vesting_account.schedules {
+            to_deposit = to_deposit.checked_add(compute_schedule_total_amount(sch)?)?;
+            // The account level flag is the default for its schedules, then the program one.
+            // It is stored on the schedule so later program updates do not change the grant.
+            if sch.clawbackable.is_none() {
+                sch.clawbackable = vesting_account.clawbackable.or(Some(program.clawbackable));
+            }
+        }
+
+        if let Some(mut old_info) =
+            VESTING_INFO.may_load(deps.storage, (program_id, token.key(), &account_address))?
+        {
+            released_amount = old_info.released_amount;
+            // Keep the existing schedules first, so their ids do not change
+            old_info.schedules.append(&mut vesting_account.schedules);
+            vesting_account.schedules = old_info.schedules;
+        }
+
+        save_vesting_info(
+            deps.storage,
+            env.block.height,
+            (program_id, token.key(), &account_address),
+            &VestingInfo {
+                schedules: vesting_account.schedules,
+                released_amount,
+            },
+        )?;
+
+        if vesting_account.mint_nft == Some(true) {
+            if let Some(token_id) = mint_nft(deps.storage, program_id, &token, &account_address)? {
+                response = response.add_attribute("nft_token_id", token_id);
+            }
+        }
+    }
+
+    if to_deposit != deposit_amount {
+        return Err(ContractError::VestingScheduleAmountError {});
+    }
+
+    add_to_totals(
+        deps.storage,
+        (program_id, token.key()),
+        &Totals {
+            deposited: to_deposit,
+            ..Totals::default()
+        },
+    )?;
+
+    Ok(response.add_attributes({
+        vec![
+            attr("action", "register_vesting_accounts"),
+            attr("program_id", program_id.to_string()),
+            attr("token", token.key()),
+            attr("deposited", to_deposit),
+        ]
+    }))
+}
+
+fn assert_vesting_schedules(
+    addr: &Addr,
+    vesting_schedules: &[VestingSchedule],
+) -> Result<(), ContractError> {
+    for sch in vesting_schedules {
+        if sch.terminated_at.is_some() {
+            return Err(ContractError::VestingScheduleError(addr.to_string()));
+        }
+
+        if let Some(end_point) = &sch.end_point {
+            if !(sch.start_point.time < end_point.time && sch.start_point.amount < end_point.amount)
+            {
+                return Err(ContractError::VestingScheduleError(addr.to_string()));
+            }
+        }
+
+        if let Some(cliff_time) = sch.cliff_time {
+            match &sch.end_point {
+                Some(end_point)
+                    if sch.start_point.time < cliff_time && cliff_time <= end_point.time => {}
+                _ => return Err(ContractError::VestingScheduleError(addr.to_string())),
+            }
+        }
+
+        // All points of a schedule must use the same time unit
+        let unit = sch.start_point.unit.clone().unwrap_or(TimeUnit::Seconds);
+        if sch
+            .points
+            .iter()
+            .flatten()
+            .chain(sch.end_point.iter())
+            .any(|point| point.unit.clone().unwrap_or(TimeUnit::Seconds) != unit)
+        {
+            return Err(ContractError::VestingScheduleUnitError(addr.to_string()));
+        }
+
+        if let Some(points) = &sch.points {
+            let end_point = sch
+                .end_point
+                .as_ref()
+                .ok_or_else(|| ContractError::VestingSchedulePointsError(addr.to_string()))?;
+            let curve: Vec<_> = iter::once(&sch.start_point)
+                .chain(points)
+                .chain(iter::once(end_point))
+                .collect();
+            if curve
+                .windows(2)
+                .any(|pair| pair[0].time >= pair[1].time || pair[0].amount > pair[1].amount)
+            {
+                return Err(ContractError::VestingSchedulePointsError(addr.to_string()));
+            }
+        }
+
+        if let Some(release_interval) = sch.release_interval {
+            match &sch.end_point {
+                Some(end_point)
+                    if release_interval != 0
+                        && (end_point.time - sch.start_point.time) % release_interval == 0 => {}
+                _ => {
+                    return Err(ContractError::VestingScheduleIntervalError(
+                        addr.to_string(),
+                    ))
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the recipients of a claim with their shares, or none for the default recipient of the
+/// vesting account.
+fn claim_recipients(
+    api: &dyn Api,
+    recipient: Option<String>,
+    splits: Option<Vec<(String, Decimal)>>,
+) -> Result<Option<Vec<(String, Decimal)>>, ContractError> {
+    match (recipient, splits) {
+        (None, None) => Ok(None),
+        (Some(recipient), None) => Ok(Some(vec![(recipient, Decimal::one())])),
+        (None, Some(splits)) => {
+            let total_share = splits
+                .iter()
+                .try_fold(Decimal::zero(), |total, (_, share)| {
+                    total.checked_add(*share)
+                })?;
+            if total_share != Decimal::one() {
+                return Err(ContractError::ClaimSplitError {});
+            }
+
+            let recipients = splits
+                .into_iter()
+                .map(|(recipient, share)| {
+                    Ok((addr_validate_to_lower(api, recipient)?.into(), share))
+                })
+                .collect::<StdResult<_>>()?;
+            Ok(Some(recipients))
+        }
+        (Some(_), Some(_)) => Err(ContractError::ClaimSplitError {}),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn claim(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipients: Option<Vec<(String, Decimal)>>,
+    amount: Option<Uint128>,
+    token: Option<Token>,
+    program_id: Option<u64>,
+    msg: Option<Binary>,
+) -> Result<Response, ContractError> {
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    let program = PROGRAMS.load(deps.storage, program_id)?;
+    let token = token_opt_validate(deps.api, token)?.unwrap_or(program.token);
+
+    let (messages, available_amount, claim_amount) = claim_available_amount(
+        deps.storage,
+        &env.block,
+        (program_id, &token),
+        &info.sender,
+        recipients,
+        amount,
+        None,
+        msg,
+    )?;
+
+    Ok(Response::new()
+        .add_submessages(messages)
+        .add_attributes(vec![
+            attr("action", "claim"),
+            attr("address", &info.sender),
+            attr("program_id", program_id.to_string()),
+            attr("token", token.key()),
+            attr("available_amount", available_amount),
+            attr("claimed_amount", claim_amount),
+        ]))
+}
+
+pub fn claim_for(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    beneficiary: String,
+    amount: Option<Uint128>,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> Result<Response, ContractError> {
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    let program = PROGRAMS.load(deps.storage, program_id)?;
+    let token = token_opt_validate(deps.api, token)?.unwrap_or(program.token);
+    let beneficiary = addr_validate_to_lower(deps.api, beneficiary)?;
+
+    // Permission check
+    let claimer_key = ((program_id, token.key()), &beneficiary, &info.sender);
+    let mut claimer = match CLAIMERS.may_load(deps.storage, claimer_key)? {
+        Some(claimer) if !claimer.expires.is_expired(&env.block) => claimer,
+        _ => return Err(ContractError::Unauthorized {}),
+    };
+
+    let (messages, available_amount, claim_amount) = claim_available_amount(
+        deps.storage,
+        &env.block,
+        (program_id, &token),
+        &beneficiary,
+        claimer
+            .recipient
+            .as_ref()
+            .map(|recipient| vec![(recipient.to_string(), Decimal::one())]),
+        amount,
+        claimer.max_amount,
+        None,
+    )?;
+
+    if let Some(max_amount) = claimer.max_amount {
+        claimer.max_amount = Some(max_amount.checked_sub(claim_amount)?);
+        CLAIMERS.save(deps.storage, claimer_key, &claimer)?;
+    }
+
+    Ok(Response::new()
+        .add_submessages(messages)
+        .add_attributes(vec![
+            attr("action", "claim_for"),
+            attr("address", &beneficiary),
+            attr("claimer", &info.sender),
+            attr("program_id", program_id.to_string()),
+            attr("token", token.key()),
+            attr("available_amount", available_amount),
+            attr("claimed_amount", claim_amount),
+        ]))
+}
+
+pub fn distribute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    addresses: Vec<Addr>,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> Result<Response, ContractError> {
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    let program = PROGRAMS.load(deps.storage, program_id)?;
+
+    // Permission check
+    if info.sender != program.owner && Some(&info.sender) != program.operator.as_ref() {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let token = token_opt_validate(deps.api, token)?.unwrap_or(program.token);
+
+    let mut response = Response::new().add_attributes(vec![
+        attr("action", "distribute"),
+        attr("program_id", program_id.to_string()),
+        attr("token", token.key()),
+    ]);
+
+    for address in addresses {
+        // One unknown address does not hold up the rest of the batch
+        if !VESTING_INFO.has(deps.storage, (program_id, token.key(), &address)) {
+            response = response.add_attribute("skipped", &address);
+            continue;
+        }
+
+        let (messages, _, claim_amount) = claim_available_amount(
+            deps.storage,
+            &env.block,
+            (program_id, &token),
+            &address,
+            None,
+            None,
+            None,
+            None,
+        )?;
+
+        response = response.add_submessages(messages).add_attributes(vec![
+            attr("address", &address),
+            attr("claimed_amount", claim_amount),
+        ]);
+    }
+
+    Ok(response)
+}
+
+/// Distributes to a page of the vesting accounts of a program and token. The last address of the
+/// page is the `start_after` of the next one.
+pub fn distribute_all(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    start_after: Option<String>,
+    limit: Option<u32>,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> Result<Response, ContractError> {
+    let start_after = addr_opt_validate(deps.api, &start_after)?;
+    let (program_id, token) = query_program_token(deps.as_ref(), program_id, token)?;
+
+    let addresses: Vec<_> = read_vesting_infos(
+        deps.as_ref(),
+        program_id,
+        &token,
+        start_after,
+        limit,
+        Some(OrderBy::Asc),
+    )?
+    .into_iter()
+    .map(|(address, _)| address)
+    .collect();
+
+    let last_address = addresses
+        .last()
+        .map(|address| address.to_string())
+        .unwrap_or_default();
+
+    Ok(
+        distribute(deps, env, info, addresses, Some(token), Some(program_id))?
+            .add_attribute("last_address", last_address),
+    )
+}
+
+/// Releases `amount` of a vesting account to `recipients` by their shares, everything available
+/// by default. Without recipients, the tokens go to the default recipient of the account or the
+/// beneficiary. A claim of everything available stops at `limit`, and a larger `amount` is
+/// rejected. The recipients are notified with `msg` when set. Returns the transfer messages with
+/// the available and claimed amounts.
+#[allow(clippy::too_many_arguments)]
+fn claim_available_amount(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    (program_id, token): (u64, &Token),
+    address: &Addr,
+    recipients: Option<Vec<(String, Decimal)>>,
+    amount: Option<Uint128>,
+    limit: Option<Uint128>,
+    msg: Option<Binary>,
+) -> Result<(Vec<SubMsg>, Uint128, Uint128), ContractError> {
+    let mut vesting_info = VESTING_INFO.load(storage, (program_id, token.key(), address))?;
+
+    let available_amount = compute_available_amount(block, &vesting_info)?;
+
+    let mut claim_amount = amount.unwrap_or(available_amount);
+    if claim_amount > available_amount {
+        return Err(ContractError::AmountIsNotAvailable {});
+    }
+    if let Some(limit) = limit {
+        if claim_amount > limit {
+            if amount.is_some() {
+                return Err(ContractError::AmountIsNotAvailable {});
+            }
+            claim_amount = limit;
+        }
+    }
+
+    let mut messages = vec![];
+
+    if !claim_amount.is_zero() {
+        let recipients = match recipients {
+            Some(recipients) => recipients,
+            None => {
+                let recipient = DEFAULT_RECIPIENTS
+                    .may_load(storage, (program_id, token.key(), address))?
+                    .unwrap_or_else(|| address.clone());
+                vec![(recipient.into(), Decimal::one())]
+            }
+        };
+        for (recipient, amount) in split_amount(claim_amount, recipients)? {
+            if !amount.is_zero() {
+                messages.push(SubMsg::new(transfer_msg(
+                    token,
+                    recipient,
+                    amount,
+                    msg.clone(),
+                )?));
+            }
+        }
+
+        vesting_info.released_amount = vesting_info.released_amount.checked_add(claim_amount)?;
+        save_vesting_info(
+            storage,
+            block.height,
+            (program_id, token.key(), address),
+            &vesting_info,
+        )?;
+        add_to_totals(
+            storage,
+            (program_id, token.key()),
+            &Totals {
+                released: claim_amount,
+                ..Totals::default()
+            },
+        )?;
+    };
+
+    Ok((messages, available_amount, claim_amount))
+}
+
+/// Splits `amount` by the shares of the recipients, so the parts add up to `amount`. The last
+/// recipient gets the rounding remainder.
+fn split_amount(
+    amount: Uint128,
+    recipients: Vec<(String, Decimal)>,
+) -> StdResult<Vec<(String, Uint128)>> {
+    let mut remaining_amount = amount;
+    let last_index = recipients.len().saturating_sub(1);
+
+    recipients
+        .into_iter()
+        .enumerate()
+        .map(|(index, (recipient, share))| {
+            let part = if index == last_index {
+                remaining_amount
+            } else {
+                amount * share
+            };
+            remaining_amount = remaining_amount.checked_sub(part)?;
+            Ok((recipient, part))
+        })
+        .collect()
+}
+
+pub fn set_default_recipient(
+    deps: DepsMut,
+    info: MessageInfo,
+    recipient: Option<String>,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> Result<Response, ContractError> {
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    let program = PROGRAMS.load(deps.storage, program_id)?;
+    let token = token_opt_validate(deps.api, token)?.unwrap_or(program.token);
+    let recipient = addr_opt_validate(deps.api, &recipient)?;
+
+    // Only the beneficiary can set the recipient of its account
+    let key = (program_id, token.key(), &info.sender);
+    if !VESTING_INFO.has(deps.storage, key) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    match &recipient {
+        Some(recipient) => DEFAULT_RECIPIENTS.save(deps.storage, key, recipient)?,
+        None => DEFAULT_RECIPIENTS.remove(deps.storage, key),
+    }
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "set_default_recipient"),
+        attr("address", &info.sender),
+        attr(
+            "recipient",
+            recipient.as_ref().map(Addr::as_str).unwrap_or_default(),
+        ),
+        attr("program_id", program_id.to_string()),
+        attr("token", token.key()),
+    ]))
+}
+
+pub fn grant_claimer(
+    deps: DepsMut,
+    info: MessageInfo,
+    claimer: String,
+    claimer_info: ClaimerInfo,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> Result<Response, ContractError> {
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    let program = PROGRAMS.load(deps.storage, program_id)?;
+    let token = token_opt_validate(deps.api, token)?.unwrap_or(program.token);
+    let claimer = addr_validate_to_lower(deps.api, claimer)?;
+
+    // Only the beneficiary can grant a claimer on its account
+    if !VESTING_INFO.has(deps.storage, (program_id, token.key(), &info.sender)) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    CLAIMERS.save(
+        deps.storage,
+        ((program_id, token.key()), &info.sender, &claimer),
+        &claimer_info,
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "grant_claimer"),
+        attr("address", &info.sender),
+        attr("claimer", &claimer),
+        attr("program_id", program_id.to_string()),
+        attr("token", token.key()),
+    ]))
+}
+
+pub fn revoke_claimer(
+    deps: DepsMut,
+    info: MessageInfo,
+    claimer: String,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> Result<Response, ContractError> {
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    let program = PROGRAMS.load(deps.storage, program_id)?;
+    let token = token_opt_validate(deps.api, token)?.unwrap_or(program.token);
+    let claimer = addr_validate_to_lower(deps.api, claimer)?;
+
+    CLAIMERS.remove(
+        deps.storage,
+        ((program_id, token.key()), &info.sender, &claimer),
+    );
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "revoke_claimer"),
+        attr("address", &info.sender),
+        attr("claimer", &claimer),
+        attr("program_id", program_id.to_string()),
+        attr("token", token.key()),
+    ]))
+}
+
+/// Builds the message that pays out `amount` of `token` to `recipient`. With a `msg`, the recipient
+/// is a contract executed with the tokens, through a CW20 `Send` or with the native funds attached.
+fn transfer_msg(
+    token: &Token,
+    recipient: impl Into<String>,
+    amount: Uint128,
+    msg: Option<Binary>,
+) -> StdResult<CosmosMsg> {
+    if let Some(msg) = msg {
+        return Ok(match token {
+            Token::Cw20(contract_addr) => WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                funds: vec![],
+                msg: to_json_binary(&Cw20ExecuteMsg::Send {
+                    contract: recipient.into(),
+                    amount,
+                    msg,
+                })?,
+            }
+            .into(),
+            Token::Native(denom) => WasmMsg::Execute {
+                contract_addr: recipient.into(),
+                funds: coins(amount.u128(), denom),
+                msg,
+            }
+            .into(),
+        });
+    }
+
+    Ok(match token {
+        Token::Cw20(contract_addr) => WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            funds: vec![],
+            msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: recipient.into(),
+                amount,
+            })?,
+        }
+        .into(),
+        Token::Native(denom) => BankMsg::Send {
+            to_address: recipient.into(),
+            amount: coins(amount.u128(), denom),
+        }
+        .into(),
+    })
+}
+
+fn compute_available_amount(block: &BlockInfo, vesting_info: &VestingInfo) -> StdResult<Uint128> {
+    compute_vested_amount(block, vesting_info)?
+        .checked_sub(vesting_info.released_amount)
+        .map_err(StdError::from)
+}
+
+/// Returns the amount of a vesting account that is not released yet, vested or not.
+fn compute_unreleased_amount(vesting_info: &VestingInfo) -> StdResult<Uint128> {
+    let mut total_amount = Uint128::zero();
+    for sch in &vesting_info.schedules {
+        total_amount = total_amount.checked_add(compute_schedule_total_amount(sch)?)?;
+    }
+
+    Ok(total_amount.checked_sub(vesting_info.released_amount)?)
+}
+
+fn compute_vested_amount(block: &BlockInfo, vesting_info: &VestingInfo) -> StdResult<Uint128> {
+    let mut vested_amount: Uint128 = Uint128::zero();
+    for sch in &vesting_info.schedules {
+        vested_amount = vested_amount.checked_add(compute_schedule_vested_amount(
+            schedule_current_time(block, sch),
+            sch,
+        )?)?;
+    }
+
+    Ok(vested_amount)
+}
+
+/// Returns the current time in the unit of the schedule, either seconds or block height.
+fn schedule_current_time(block: &BlockInfo, sch: &VestingSchedule) -> u64 {
+    match sch.start_point.unit {
+        Some(TimeUnit::Height) => block.height,
+        Some(TimeUnit::Seconds) | None => block.time.seconds(),
+    }
+}
+
+/// Returns the amount vested by a single schedule, which stops growing once the schedule is terminated.
+fn compute_schedule_vested_amount(current_time: u64, sch: &VestingSchedule) -> StdResult<Uint128> {
+    let current_time = sch.terminated_at.map_or(current_time, |terminated_at| {
+        terminated_at.min(current_time)
+    });
+
+    if sch.start_point.time > current_time {
+        return Ok(Uint128::zero());
+    }
+
+    // Nothing is vested before the cliff, then the amount accrued since the start unlocks at once
+    if let Some(cliff_time) = sch.cliff_time {
+        if cliff_time > current_time {
+            return Ok(Uint128::zero());
+        }
+    }
+
+    let end_point = match &sch.end_point {
+        Some(end_point) => end_point,
+        None => return Ok(sch.start_point.amount),
+    };
+
+    let mut current_time = current_time.min(end_point.time);
+    // Periodic schedules only release at the end of each interval
+    if let Some(release_interval) = sch.release_interval {
+        current_time -= (current_time - sch.start_point.time) % release_interval;
+    }
+
+    // Interpolate between the points around the current time
+    let mut previous_point = &sch.start_point;
+    for point in sch.points.iter().flatten().chain(iter::once(end_point)) {
+        if current_time < point.time {
+            let release_amount = Uint128::from(current_time - previous_point.time).multiply_ratio(
+                point.amount.checked_sub(previous_point.amount)?,
+                point.time - previous_point.time,
+            );
+            return Ok(previous_point.amount.checked_add(release_amount)?);
+        }
+        previous_point = point;
+    }
+
+    Ok(end_point.amount)
+}
+
+/// Returns the amount a single schedule vests in total, which is cut at the termination time.
+fn compute_schedule_total_amount(sch: &VestingSchedule) -> StdResult<Uint128> {
+    if let Some(terminated_at) = sch.terminated_at {
+        return compute_schedule_vested_amount(terminated_at, sch);
+    }
+
+    if let Some(end_point) = &sch.end_point {
+        Ok(end_point.amount)
+    } else {
+        Ok(sch.start_point.amount)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn clawback(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipient: Addr,
+    amount: Option<Uint128>,
+    schedule_ids: Option<Vec<u32>>,
+    clawback_recipient: Option<String>,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> Result<Response, ContractError> {
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    let program = PROGRAMS.load(deps.storage, program_id)?;
+    let token = token_opt_validate(deps.api, token)?.unwrap_or(program.token);
+
+    // Permission check
+    if info.sender != program.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let clawback_recipient = addr_opt_validate(deps.api, &clawback_recipient)?
+        .or(program.clawback_recipient)
+        .unwrap_or(info.sender);
+
+    let mut vesting_info =
+        VESTING_INFO.load(deps.storage, (program_id, token.key(), &recipient))?;
+
+    let schedule_ids = match schedule_ids {
+        Some(mut ids) => {
+            ids.sort_unstable();
+            ids.dedup();
+            if let Some(id) = ids
+                .iter()
+                .find(|id| **id as usize >= vesting_info.schedules.len())
+            {
+                return Err(ContractError::VestingScheduleNotFound(*id));
+            }
+            if ids
+                .iter()
+                .any(|id| !is_clawbackable(&vesting_info.schedules[*id as usize]))
+            {
+                return Err(ContractError::Unauthorized {});
+            }
+            ids.into_iter().map(|id| id as usize).collect()
+        }
+        None => (0..vesting_info.schedules.len())
+            .filter(|id| is_clawbackable(&vesting_info.schedules[*id]))
+            .collect::<Vec<_>>(),
+    };
+
+    if schedule_ids.is_empty() {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // Terminated schedules have nothing left to claw back
+    let schedule_ids = schedule_ids
+        .into_iter()
+        .filter(|id| vesting_info.schedules[*id].terminated_at.is_none())
+        .collect::<Vec<_>>();
+
+    // The vested but unclaimed part of the schedules that are terminated or reduced belongs to the
+    // beneficiary, only the unvested part goes back. The other schedules stay claimable as usual.
+    let mut vested_amount = Uint128::zero();
+    let available_amount = compute_available_amount(&env.block, &vesting_info)?;
+
+    let clawback_amount = if let Some(amount) = amount {
+        // Take the requested amount from the latest schedules first and keep the rest vesting
+        let mut remaining_amount = amount;
+        for id in schedule_ids.into_iter().rev() {
+            if remaining_amount.is_zero() {
+                break;
+            }
+
+            let sch = &mut vesting_info.schedules[id];
+            let current_time = schedule_current_time(&env.block, sch);
+            let unvested_amount = compute_available_clawback_amount(current_time, sch)?;
+            if unvested_amount.is_zero() {
+                continue;
+            }
+
+            vested_amount =
+                vested_amount.checked_add(compute_schedule_vested_amount(current_time, sch)?)?;
+            if remaining_amount >= unvested_amount {
+                sch.terminated_at = Some(current_time);
+                remaining_amount -= unvested_amount;
+            } else {
+                reduce_vesting_schedule(current_time, sch, remaining_amount)?;
+                remaining_amount = Uint128::zero();
+            }
+        }
+
+        if !remaining_amount.is_zero() {
+            return Err(ContractError::AmountIsNotAvailable {});
+        }
+
+        amount
+    } else {
+        // End the selected schedules, so the clawed back amount is no longer part of the grant.
+        // Schedules that have not started yet are cancelled this way.
+        let mut clawback_amount = Uint128::zero();
+        for id in schedule_ids {
+            let sch = &mut vesting_info.schedules[id];
+            let current_time = schedule_current_time(&env.block, sch);
+            clawback_amount = clawback_amount
+                .checked_add(compute_available_clawback_amount(current_time, sch)?)?;
+            vested_amount =
+                vested_amount.checked_add(compute_schedule_vested_amount(current_time, sch)?)?;
+            sch.terminated_at = Some(current_time);
+        }
+
+        clawback_amount
+    };
+    let vested_amount = vested_amount.min(available_amount);
+
+    if clawback_amount.is_zero() {
+        return Err(ContractError::NothingToClawBack {});
+    }
+
+    let mut response = Response::new();
+
+    if !vested_amount.is_zero() {
+        // Paid like a claim of the beneficiary
+        let payout_address = DEFAULT_RECIPIENTS
+            .may_load(deps.storage, (program_id, token.key(), &recipient))?
+            .unwrap_or_else(|| recipient.clone());
+        response = response.add_submessage(SubMsg::new(transfer_msg(
+            &token,
+            &payout_address,
+            vested_amount,
+            None,
+        )?));
+    }
+
+    if !clawback_amount.is_zero() {
+        response = response.add_submessage(SubMsg::new(transfer_msg(
+            &token,
+            &clawback_recipient,
+            clawback_amount,
+            None,
+        )?));
+    }
+
+    vesting_info.released_amount = vesting_info.released_amount.checked_add(vested_amount)?;
+    save_vesting_info(
+        deps.storage,
+        env.block.height,
+        (program_id, token.key(), &recipient),
+        &vesting_info,
+    )?;
+    add_to_totals(
+        deps.storage,
+        (program_id, token.key()),
+        &Totals {
+            released: vested_amount,
+            clawed_back: clawback_amount,
+            ..Totals::default()
+        },
+    )?;
+
+    Ok(response.add_attributes(vec![
+        attr("action", "clawback"),
+        attr("address", &recipient),
+        attr("program_id", program_id.to_string()),
+        attr("token", token.key()),
+        attr("clawback_recipient", &clawback_recipient),
+        attr("vested_amount", vested_amount),
+        attr("clawback_amount", clawback_amount),
+    ]))
+}
+
+/// Schedules registered without a clawbackable flag can be clawed back.
+fn is_clawbackable(sch: &VestingSchedule) -> bool {
+    sch.clawbackable.unwrap_or(true)
+}
+
+/// Returns the unvested amount of a schedule, which is what the owner can claw back.
+/// Schedules that have not started yet can be clawed back in full.
+fn compute_available_clawback_amount(
+    current_time: u64,
+    sch: &VestingSchedule,
+) -> StdResult<Uint128> {
+    compute_schedule_total_amount(sch)?
+        .checked_sub(compute_schedule_vested_amount(current_time, sch)?)
+        .map_err(StdError::from)
+}
+
+/// Takes `amount` out of the unvested part of a schedule. The rest keeps vesting until the
+/// original end time, and the amount vested so far is left untouched.
+fn reduce_vesting_schedule(
+    current_time: u64,
+    sch: &mut VestingSchedule,
+    amount: Uint128,
+) -> StdResult<()> {
+    let total_amount = compute_schedule_total_amount(sch)?;
+    let vested_amount = compute_schedule_vested_amount(current_time, sch)?;
+    let new_total_amount = total_amount.checked_sub(amount)?;
+
+    // Every point after the new start keeps its share of the unvested amount
+    let reduce = |point_amount: Uint128| -> StdResult<Uint128> {
+        Ok(
+            vested_amount.checked_add(point_amount.checked_sub(vested_amount)?.multiply_ratio(
+                new_total_amount.checked_sub(vested_amount)?,
+                total_amount.checked_sub(vested_amount)?,
+            ))?,
+        )
+    };
+
+    if vested_amount.is_zero() {
+        // Nothing is vested yet, so the whole schedule is scaled down
+        sch.start_point.amount = reduce(sch.start_point.amount)?;
+    } else {
+        // Vest the rest from now on, or from the last release of a periodic schedule.
+        // The cliff has passed already.
+        let start_time = match sch.release_interval {
+            Some(release_interval) => {
+                current_time - (current_time - sch.start_point.time) % release_interval
+            }
+            None => current_time,
+        };
+        sch.start_point = VestingSchedulePoint {
+            time: start_time,
+            amount: vested_amount,
+            unit: sch.start_point.unit.clone(),
+        };
+        sch.cliff_time = None;
+    }
+
+    if let Some(points) = &mut sch.points {
+        points.retain(|point| point.time > sch.start_point.time);
+        for point in points {
+            point.amount = reduce(point.amount)?;
+        }
+    }
+
+    if let Some(end_point) = &mut sch.end_point {
+        end_point.amount = new_total_amount;
+    }
+
+    Ok(())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Config {} => Ok(to_json_binary(&query_config(deps)?)?),
+        QueryMsg::Program { program_id } => Ok(to_json_binary(&query_program(deps, program_id)?)?),
+        QueryMsg::Programs {
+            start_after,
+            limit,
+            order_by,
+        } => Ok(to_json_binary(&query_programs(
+            deps,
+            start_after,
+            limit,
+            order_by,
+        )?)?),
+        QueryMsg::AllowedTokens { program_id } => {
+            Ok(to_json_binary(&query_allowed_tokens(deps, program_id)?)?)
+        }
+        QueryMsg::VestingAccount {
+            address,
+            token,
+            program_id,
+        } => Ok(to_json_binary(&query_vesting_account(
+            deps, address, token, program_id,
+        )?)?),
+        QueryMsg::VestingAccounts {
+            start_after,
+            limit,
+            order_by,
+            token,
+            program_id,
+        } => Ok(to_json_binary(&query_vesting_accounts(
+            deps,
+            start_after,
+            limit,
+            order_by,
+            token,
+            program_id,
+        )?)?),
+        QueryMsg::AvailableAmount {
+            address,
+            token,
+            program_id,
+        } => Ok(to_json_binary(&query_vesting_available_amount(
+            deps, env, address, token, program_id,
+        )?)?),
+        QueryMsg::Claimer {
+            beneficiary,
+            claimer,
+            token,
+            program_id,
+        } => Ok(to_json_binary(&query_claimer(
+            deps,
+            beneficiary,
+            claimer,
+            token,
+            program_id,
+        )?)?),
+        QueryMsg::PendingTransfer {
+            address,
+            token,
+            program_id,
+        } => Ok(to_json_binary(&query_pending_transfer(
+            deps, address, token, program_id,
+        )?)?),
+        QueryMsg::VotingPowerAtHeight {
+            address,
+            height,
+            token,
+            program_id,
+        } => Ok(to_json_binary(&query_voting_power_at_height(
+            deps, env, address, height, token, program_id,
+        )?)?),
+        QueryMsg::TotalPowerAtHeight {
+            height,
+            token,
+            program_id,
+        } => Ok(to_json_binary(&query_total_power_at_height(
+            deps, env, height, token, program_id,
+        )?)?),
+        QueryMsg::UnreleasedAt {
+            address,
+            height,
+            token,
+            program_id,
+        } => Ok(to_json_binary(&query_account_amount_at(
+            deps,
+            UNRELEASED_AMOUNTS,
+            address,
+            height,
+            token,
+            program_id,
+        )?)?),
+        QueryMsg::ReleasedAt {
+            address,
+            height,
+            token,
+            program_id,
+        } => Ok(to_json_binary(&query_account_amount_at(
+            deps,
+            RELEASED_AMOUNTS,
+            address,
+            height,
+            token,
+            program_id,
+        )?)?),
+        QueryMsg::TotalUnreleasedAt {
+            height,
+            token,
+            program_id,
+        } => Ok(to_json_binary(
+            &query_total_power_at_height(deps, env, Some(height), token, program_id)?.power,
+        )?),
+        QueryMsg::Totals { token, program_id } => {
+            Ok(to_json_binary(&query_totals(deps, token, program_id)?)?)
+        }
+        QueryMsg::Invariants { token } => Ok(to_json_binary(&query_invariants(deps, env, token)?)?),
+        QueryMsg::Timestamp {} => Ok(to_json_binary(&query_timestamp(env)?)?),
+        QueryMsg::OwnerOf { token_id, .. } => Ok(to_json_binary(&query_owner_of(deps, token_id)?)?),
+        QueryMsg::NftInfo { token_id } => Ok(to_json_binary(&query_nft_info(deps, token_id)?)?),
+        QueryMsg::AllNftInfo { token_id, .. } => Ok(to_json_binary(&AllNftInfoResponse {
+            access: query_owner_of(deps, token_id.clone())?,
+            info: query_nft_info(deps, token_id)?,
+        })?),
+        QueryMsg::Tokens {
+            owner,
+            start_after,
+            limit,
+        } => Ok(to_json_binary(&query_tokens(
+            deps,
+            Some(owner),
+            start_after,
+            limit,
+        )?)?),
+        QueryMsg::AllTokens { start_after, limit } => Ok(to_json_binary(&query_tokens(
+            deps,
+            None,
+            start_after,
+            limit,
+        )?)?),
+        QueryMsg::NumTokens {} => Ok(to_json_binary(&NumTokensResponse {
+            count: NFT_COUNT.may_load(deps.storage)?.unwrap_or_default(),
+        })?),
+        QueryMsg::ContractInfo {} => Ok(to_json_binary(&ContractInfoResponse {
+            name: NFT_NAME.to_string(),
+            symbol: NFT_SYMBOL.to_string(),
+        })?),
+    }
+}
+
+pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
+    let config = CONFIG.load(deps.storage)?;
+
+    Ok(ConfigResponse {
+        owner: config.owner,
+    })
+}
+
+fn program_response(program_id: u64, program: Program) -> ProgramResponse {
+    ProgramResponse {
+        program_id,
+        owner: program.owner,
+        token: program.token,
+        clawback_recipient: program.clawback_recipient,
+        clawbackable: program.clawbackable,
+        transfer_policy: program.transfer_policy,
+        operator: program.operator,
+    }
+}
+
+pub fn query_program(deps: Deps, program_id: u64) -> StdResult<ProgramResponse> {
+    let program = PROGRAMS.load(deps.storage, program_id)?;
+
+    Ok(program_response(program_id, program))
+}
+
+pub fn query_programs(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+    order_by: Option<OrderBy>,
+) -> StdResult<ProgramsResponse> {
+    let programs = read_programs(deps, start_after, limit, order_by)?
+        .into_iter()
+        .map(|(program_id, program)| program_response(program_id, program))
+        .collect();
+
+    Ok(ProgramsResponse { programs })
+}
+
+pub fn query_allowed_tokens(deps: Deps, program_id: Option<u64>) -> StdResult<Vec<Token>> {
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    let program = PROGRAMS.load(deps.storage, program_id)?;
+
+    iter::once(Ok(program.token))
+        .chain(
+            ALLOWED_TOKENS
+                .prefix(program_id)
+                .range(deps.storage, None, None, Order::Ascending)
+                .map(|item| item.map(|(_, token)| token)),
+        )
+        .collect()
+}
+
+pub fn query_timestamp(env: Env) -> StdResult<u64> {
+    Ok(env.block.time.seconds())
+}
+
+/// Returns the selected program, or the default one, and the selected token, or the token of the
+/// program.
+fn query_program_token(
+    deps: Deps,
+    program_id: Option<u64>,
+    token: Option<Token>,
+) -> StdResult<(u64, Token)> {
+    let program_id = program_id.unwrap_or(DEFAULT_PROGRAM_ID);
+    match token_opt_validate(deps.api, token)? {
+        Some(token) => Ok((program_id, token)),
+        None => Ok((program_id, PROGRAMS.load(deps.storage, program_id)?.token)),
+    }
+}
+
+fn vesting_account_response(
+    deps: Deps,
+    address: Addr,
+    program_id: u64,
+    token: Token,
+    info: VestingInfo,
+) -> StdResult<VestingAccountResponse> {
+    let default_recipient =
+        DEFAULT_RECIPIENTS.may_load(deps.storage, (program_id, token.key(), &address))?;
+
+    Ok(VestingAccountResponse {
+        address,
+        program_id,
+        token,
+        info,
+        default_recipient,
+    })
+}
+
+pub fn query_vesting_account(
+    deps: Deps,
+    address: String,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> StdResult<VestingAccountResponse> {
+    let address = addr_validate_to_lower(deps.api, &address)?;
+    let (program_id, token) = query_program_token(deps, program_id, token)?;
+    let info = VESTING_INFO.load(deps.storage, (program_id, token.key(), &address))?;
+
+    vesting_account_response(deps, address, program_id, token, info)
+}
+
+pub fn query_vesting_accounts(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+    order_by: Option<OrderBy>,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> StdResult<VestingAccountsResponse> {
+    let start_after = addr_opt_validate(deps.api, &start_after)?;
+    let (program_id, token) = query_program_token(deps, program_id, token)?;
+
+    let vesting_infos = read_vesting_infos(deps, program_id, &token, start_after, limit, order_by)?;
+
+    let vesting_accounts = vesting_infos
+        .into_iter()
+        .map(|(address, info)| {
+            vesting_account_response(deps, address, program_id, token.clone(), info)
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(VestingAccountsResponse { vesting_accounts })
+}
+
+pub fn query_vesting_available_amount(
+    deps: Deps,
+    env: Env,
+    address: String,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> StdResult<Uint128> {
+    let address = addr_validate_to_lower(deps.api, &address)?;
+    let (program_id, token) = query_program_token(deps, program_id, token)?;
+
+    let info = VESTING_INFO.load(deps.storage, (program_id, token.key(), &address))?;
+    let available_amount = compute_available_amount(&env.block, &info)?;
+    Ok(available_amount)
+}
+
+pub fn query_claimer(
+    deps: Deps,
+    beneficiary: String,
+    claimer: String,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> StdResult<Option<ClaimerInfo>> {
+    let beneficiary = addr_validate_to_lower(deps.api, &beneficiary)?;
+    let claimer = addr_validate_to_lower(deps.api, &claimer)?;
+    let (program_id, token) = query_program_token(deps, program_id, token)?;
+
+    CLAIMERS.may_load(
+        deps.storage,
+        ((program_id, token.key()), &beneficiary, &claimer),
+    )
+}
+
+pub fn query_pending_transfer(
+    deps: Deps,
+    address: String,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> StdResult<Option<Addr>> {
+    let address = addr_validate_to_lower(deps.api, &address)?;
+    let (program_id, token) = query_program_token(deps, program_id, token)?;
+
+    PENDING_TRANSFERS.may_load(deps.storage, (program_id, token.key(), &address))
+}
+
+pub fn query_voting_power_at_height(
+    deps: Deps,
+    env: Env,
+    address: String,
+    height: Option<u64>,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> StdResult<VotingPowerAtHeightResponse> {
+    let height = height.unwrap_or(env.block.height);
+    let power = if program_id.is_some() {
+        query_account_amount_at(deps, UNRELEASED_AMOUNTS, address, height, token, program_id)?
+    } else {
+        // Voting modules do not select a program, so the power adds up across all programs
+        let address = addr_validate_to_lower(deps.api, &address)?;
+        let (_, token) = query_program_token(deps, None, token)?;
+        sum_over_programs(deps, |program_id| {
+            UNRELEASED_AMOUNTS.may_load_at_height(
+                deps.storage,
+                (program_id, token.key(), &address),
+                height,
+            )
+        })?
+    };
+
+    Ok(VotingPowerAtHeightResponse { power, height })
+}
+
+/// Adds up an optional amount of every program.
+fn sum_over_programs(
+    deps: Deps,
+    amount: impl Fn(u64) -> StdResult<Option<Uint128>>,
+) -> StdResult<Uint128> {
+    PROGRAMS
+        .keys(deps.storage, None, None, Order::Ascending)
+        .try_fold(Uint128::zero(), |total, program_id| {
+            Ok(total.checked_add(amount(program_id?)?.unwrap_or_default())?)
+        })
+}
+
+/// Returns a snapshotted amount of a vesting account at the start of the block at `height`.
+fn query_account_amount_at(
+    deps: Deps,
+    amounts: SnapshotMap<(u64, &str, &Addr), Uint128>,
+    address: String,
+    height: u64,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> StdResult<Uint128> {
+    let address = addr_validate_to_lower(deps.api, &address)?;
+    let (program_id, token) = query_program_token(deps, program_id, token)?;
+
+    Ok(amounts
+        .may_load_at_height(deps.storage, (program_id, token.key(), &address), height)?
+        .unwrap_or_default())
+}
+
+pub fn query_total_power_at_height(
+    deps: Deps,
+    env: Env,
+    height: Option<u64>,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> StdResult<TotalPowerAtHeightResponse> {
+    let height = height.unwrap_or(env.block.height);
+
+    let power = if program_id.is_some() {
+        let (program_id, token) = query_program_token(deps, program_id, token)?;
+        TOTAL_UNRELEASED_AMOUNTS
+            .may_load_at_height(deps.storage, (program_id, token.key()), height)?
+            .unwrap_or_default()
+    } else {
+        let (_, token) = query_program_token(deps, None, token)?;
+        sum_over_programs(deps, |program_id| {
+            TOTAL_UNRELEASED_AMOUNTS.may_load_at_height(
+                deps.storage,
+                (program_id, token.key()),
+                height,
+            )
+        })?
+    };
+
+    Ok(TotalPowerAtHeightResponse { power, height })
+}
+
+pub fn query_totals(
+    deps: Deps,
+    token: Option<Token>,
+    program_id: Option<u64>,
+) -> StdResult<TotalsResponse> {
+    let (program_id, token) = query_program_token(deps, program_id, token)?;
+    let totals = TOTALS
+        .may_load(deps.storage, (token.key(), program_id))?
+        .unwrap_or_default();
+
+    Ok(TotalsResponse {
+        program_id,
+        outstanding: totals.outstanding()?,
+        token,
+        deposited: totals.deposited,
+        released: totals.released,
+        clawed_back: totals.clawed_back,
+    })
+}
+
+pub fn query_invariants(
+    deps: Deps,
+    env: Env,
+    token: Option<Token>,
+) -> StdResult<InvariantsResponse> {
+    let (_, token) = query_program_token(deps, None, token)?;
+    let balance = query_token_balance(deps, &token, &env.contract.address)?;
+    let outstanding = read_outstanding_amount(deps, token.key())?;
+    let tracked_outstanding = read_tracked_outstanding_amount(deps, token.key())?;
+
+    Ok(InvariantsResponse {
+        token,
+        balance,
+        outstanding,
+        tracked_outstanding,
+        totals_mismatch: outstanding != tracked_outstanding,
+        surplus: balance.saturating_sub(outstanding),
+        deficit: outstanding.saturating_sub(balance),
+    })
+}
+
+/// Returns the balance of `address` in a CW20 or native token.
+fn query_token_balance(deps: Deps, token: &Token, address: &Addr) -> StdResult<Uint128> {
+    match token {
+        Token::Cw20(contract_addr) => {
+            let res: Cw20BalanceResponse = deps.querier.query_wasm_smart(
+                contract_addr,
+                &Cw20QueryMsg::Balance {
+                    address: address.to_string(),
+                },
+            )?;
+            Ok(res.balance)
+        }
+        Token::Native(denom) => Ok(deps.querier.query_balance(address, denom)?.amount),
+    }
+}
+
+pub fn query_owner_of(deps: Deps, token_id: String) -> StdResult<OwnerOfResponse> {
+    let nft = nfts().load(deps.storage, &token_id)?;
+
+    Ok(OwnerOfResponse {
+        owner: nft.owner.to_string(),
+        approvals: vec![],
+    })
+}
+
+pub fn query_nft_info(deps: Deps, token_id: String) -> StdResult<NftInfoResponse> {
+    let nft = nfts().load(deps.storage, &token_id)?;
+    let info = VESTING_INFO.load(deps.storage, (nft.program_id, nft.token.key(), &nft.owner))?;
+
+    Ok(NftInfoResponse {
+        token_uri: None,
+        extension: vesting_account_response(deps, nft.owner, nft.program_id, nft.token, info)?,
+    })
+}
+
+pub fn query_tokens(
+    deps: Deps,
+    owner: Option<String>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<TokensResponse> {
+    let owner = addr_opt_validate(deps.api, &owner)?;
+    let tokens = read_nft_ids(deps, owner, start_after, limit)?;
+
+    Ok(TokensResponse { tokens })
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, env: Env, msg: MigrateMsg) -> StdResult<Response> {
+    migrate_config(deps.storage)?;
+
+    // Legacy accounts all belong to the default program
+    let program = PROGRAMS.load(deps.storage, DEFAULT_PROGRAM_ID)?;
+    let token_key = program.token.key();
+
+    migrate_vesting_infos(deps.storage, &program.token, &program.owner)?;
+
+    // Vested amounts the legacy clawbacks sent to the owner instead of the beneficiary
+    let mut legacy_clawed_back_amounts = HashMap::new();
+    for account in msg.clawed_back_accounts.unwrap_or_default() {
+        let address = addr_validate_to_lower(deps.api, &account.address)?;
+        let mut vesting_info =
+            VESTING_INFO.load(deps.storage, (DEFAULT_PROGRAM_ID, token_key, &address))?;
+
+        // Legacy clawbacks sent everything left in the started schedules to the owner.
+        // Schedules from that time all use seconds.
+        let clawback_block = BlockInfo {
+            time: Timestamp::from_seconds(account.clawed_back_at),
+            ..env.block.clone()
+        };
+        let vested_amount = compute_vested_amount(&clawback_block, &vesting_info)?;
+        for sch in vesting_info.schedules.iter_mut().filter(|sch| {
+            sch.start_point.time <= account.clawed_back_at && sch.terminated_at.is_none()
+        }) {
+            sch.terminated_at = Some(account.clawed_back_at);
+        }
+        legacy_clawed_back_amounts.insert(
+            address.clone(),
+            vested_amount.saturating_sub(vesting_info.released_amount),
+        );
+        vesting_info.released_amount = vesting_info.released_amount.max(vested_amount);
+
+        VESTING_INFO.save(
+            deps.storage,
+            (DEFAULT_PROGRAM_ID, token_key, &address),
+            &vesting_info,
+        )?;
+    }
+
+    // Snapshot the amounts of the accounts registered before they were tracked, and compute the
+    // totals unless they are tracked already
+    let compute_totals = TOTALS
+        .keys(deps.storage, None, None, Order::Ascending)
+        .next()
+        .is_none();
+    let vesting_infos = VESTING_INFO
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for ((program_id, token_key, address), vesting_info) in vesting_infos {
+        snapshot_vesting_amounts(
+            deps.storage,
+            env.block.height,
+            (program_id, &token_key, &address),
+            Some(&vesting_info),
+        )?;
+
+        if compute_totals {
+            // The amounts clawed back by reducing a schedule are not recorded in it
+            let mut amounts = Totals {
+                released: vesting_info.released_amount,
+                ..Totals::default()
+            };
+            if program_id == DEFAULT_PROGRAM_ID && token_key == program.token.key() {
+                if let Some(amount) = legacy_clawed_back_amounts.get(&address) {
+                    amounts.released = amounts.released.checked_sub(*amount)?;
+                    amounts.clawed_back = *amount;
+                }
+            }
+            for sch in &vesting_info.schedules {
+                let full_amount = sch
+                    .end_point
+                    .as_ref()
+                    .map_or(sch.start_point.amount, |end_point| end_point.amount);
+                amounts.deposited = amounts.deposited.checked_add(full_amount)?;
+                amounts.clawed_back = amounts
+                    .clawed_back
+                    .checked_add(full_amount.checked_sub(compute_schedule_total_amount(sch)?)?)?;
+            }
+            add_to_totals(deps.storage, (program_id, &token_key), &amounts)?;
+        }
+    }
+
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
     Ok(Response::default())
 }
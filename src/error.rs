@@ -1,4 +1,5 @@
 use cosmwasm_std::{OverflowError, StdError};
+use cw_utils::PaymentError;
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -6,16 +7,45 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("{0}")]
+    Payment(#[from] PaymentError),
+
     #[error("Unauthorized")]
     Unauthorized {},
 
+    #[error("Invalid token")]
+    InvalidToken {},
+
     #[error("Amount is not available!")]
     AmountIsNotAvailable {},
 
-    #[error("Vesting schedule error on addr: {0}. Should satisfy: (start < end and at_start < total) or (start = end and at_start = total)")]
+    #[error("Amount should not be zero")]
+    ZeroAmount {},
+
+    #[error("Claim split shares should sum to one, without a recipient")]
+    ClaimSplitError {},
+
+    #[error("Vesting schedule error on addr: {0}. Should satisfy: (start < end and at_start < total) or (start = end and at_start = total), and start < cliff <= end")]
     VestingScheduleError(String),
 
-    #[error("Vesting schedule amount error. The total amount should be equal to the CW20 receive amount.")]
+    #[error("Vesting schedule error on addr: {0}. The release interval should divide the time between the start and end points")]
+    VestingScheduleIntervalError(String),
+
+    #[error("Vesting schedule error on addr: {0}. The points should lie between the start and end points, with increasing time and non-decreasing amount")]
+    VestingSchedulePointsError(String),
+
+    #[error("Vesting schedule error on addr: {0}. All points should use the same time unit")]
+    VestingScheduleUnitError(String),
+
+    #[error("Nothing to claw back")]
+    NothingToClawBack {},
+
+    #[error("Vesting schedule {0} not found")]
+    VestingScheduleNotFound(u32),
+
+    #[error(
+        "Vesting schedule amount error. The total amount should be equal to the deposited amount."
+    )]
     VestingScheduleAmountError {},
 }
 
@@ -1,5 +1,6 @@
 pub mod contract;
 pub mod error;
+pub mod migration;
 pub mod msg;
 pub mod state;
 pub mod util;
@@ -0,0 +1,103 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Addr, Order, StdResult, Storage, Uint128};
+use cw_storage_plus::{Item, Map};
+
+use crate::msg::{Token, TransferPolicy, VestingInfo, VestingSchedule};
+use crate::state::{
+    Config, Program, CONFIG, DEFAULT_PROGRAM_ID, PROGRAMS, PROGRAM_COUNT, VESTING_INFO,
+};
+
+/// Config as stored before native tokens could be vested.
+#[cw_serde]
+pub struct ConfigV100 {
+    pub owner: Addr,
+    pub token_addr: Addr,
+    pub clawback_recipient: Option<Addr>,
+}
+
+pub const CONFIG_V100: Item<ConfigV100> = Item::new("config");
+
+/// Vesting info as stored before the clawbackable flag was moved to the schedules.
+/// It also reads the current format, since the account level flag is then missing.
+#[cw_serde]
+pub struct VestingInfoV100 {
+    pub schedules: Vec<VestingSchedule>,
+    pub released_amount: Uint128,
+    pub clawbackable: Option<bool>,
+}
+
+/// Vesting info by beneficiary, as stored before several tokens could be vested.
+pub const VESTING_INFO_V100: Map<&Addr, VestingInfoV100> = Map::new("vesting_info");
+
+/// Turns a legacy config into the default program, which vests its CW20 token.
+pub fn migrate_config(storage: &mut dyn Storage) -> StdResult<()> {
+    if let Ok(legacy_config) = CONFIG_V100.load(storage) {
+        CONFIG.save(
+            storage,
+            &Config {
+                owner: legacy_config.owner.clone(),
+            },
+        )?;
+        PROGRAMS.save(
+            storage,
+            DEFAULT_PROGRAM_ID,
+            &Program {
+                owner: legacy_config.owner,
+                token: Token::Cw20(legacy_config.token_addr),
+                clawback_recipient: legacy_config.clawback_recipient,
+                clawbackable: true,
+                transfer_policy: TransferPolicy::Beneficiary,
+                operator: None,
+            },
+        )?;
+        PROGRAM_COUNT.save(storage, &(DEFAULT_PROGRAM_ID + 1))?;
+    }
+
+    Ok(())
+}
+
+/// Moves the legacy vesting infos under the default program and its token, and copies the
+/// account level clawbackable flag onto the schedules that do not set their own.
+///
+/// Legacy clawbacks saved a copy of the clawed back account under the owner's key. An owner
+/// account with the same schedules as another account is such a copy, so it is dropped.
+pub fn migrate_vesting_infos(
+    storage: &mut dyn Storage,
+    token: &Token,
+    owner: &Addr,
+) -> StdResult<()> {
+    let legacy_infos = VESTING_INFO_V100
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let is_clawback_copy = |address: &Addr, legacy_info: &VestingInfoV100| {
+        address == owner
+            && legacy_infos
+                .iter()
+                .any(|(other, info)| other != owner && info.schedules == legacy_info.schedules)
+    };
+
+    for (address, legacy_info) in &legacy_infos {
+        VESTING_INFO_V100.remove(storage, address);
+        if is_clawback_copy(address, legacy_info) {
+            continue;
+        }
+
+        let mut schedules = legacy_info.schedules.clone();
+        for sch in &mut schedules {
+            if sch.clawbackable.is_none() {
+                sch.clawbackable = legacy_info.clawbackable;
+            }
+        }
+
+        VESTING_INFO.save(
+            storage,
+            (DEFAULT_PROGRAM_ID, token.key(), address),
+            &VestingInfo {
+                schedules,
+                released_amount: legacy_info.released_amount,
+            },
+        )?;
+    }
+
+    Ok(())
+}
@@ -1,6 +1,9 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Addr, Order, Uint128};
+use cosmwasm_std::{
+    to_json_binary, Addr, Binary, CosmosMsg, Decimal, Order, StdResult, Uint128, WasmMsg,
+};
 use cw20::Cw20ReceiveMsg;
+use cw_utils::Expiration;
 
 #[cw_serde]
 pub enum OrderBy {
@@ -19,34 +22,211 @@ impl Into<Order> for OrderBy {
     }
 }
 
+#[cw_serde]
+pub enum Token {
+    Cw20(Addr),
+    Native(String),
+}
+
+impl Token {
+    /// Returns the CW20 contract address or the native denom, which keys the vesting accounts
+    pub fn key(&self) -> &str {
+        match self {
+            Token::Cw20(contract_addr) => contract_addr.as_str(),
+            Token::Native(denom) => denom,
+        }
+    }
+}
+
+/// Who decides on the transfer of a vesting account to a new address
+#[cw_serde]
+pub enum TransferPolicy {
+    /// The beneficiary transfers the account alone
+    Beneficiary,
+    /// The program owner approves the transfers requested by the beneficiaries
+    OwnerApproval,
+}
+
+/// Change of an optional address of a program
+#[cw_serde]
+pub enum AddrUpdate {
+    Set(String),
+    /// Falls back to the default, as if the address was never set
+    Clear {},
+}
+
+/// Creates the contract with its default program (id 0), owned by the contract owner
 #[cw_serde]
 pub struct InstantiateMsg {
     pub owner: String,
-    pub token_addr: String,
+    pub token: Token,
+    pub clawback_recipient: Option<String>,
 }
 
 #[cw_serde]
 pub enum ExecuteMsg {
     Claim {
         recipient: Option<String>,
+        /// Shares of the claimed amount going to several recipients instead of a single one,
+        /// which must sum to one. The last recipient gets the rounding remainder.
+        splits: Option<Vec<(String, Decimal)>>,
         amount: Option<Uint128>,
+        /// Token to claim, the token of the program when not set
+        token: Option<Token>,
+        /// Program of the vesting account, the default program when not set
+        program_id: Option<u64>,
+        /// Executes the recipients with the claimed tokens and this message, through a CW20
+        /// `Send` or a native funds transfer, instead of only transferring them
+        msg: Option<Binary>,
     },
+    /// Claws back the unvested part of the clawbackable schedules of an account. The vested but
+    /// unclaimed part of the clawed back schedules is paid out, capped by the amount the account
+    /// can claim, while the other schedules are left untouched.
     Clawback {
         recipient: Addr,
+        /// Amount to claw back, taken from the latest schedules first. Everything by default.
+        amount: Option<Uint128>,
+        /// Ids (indexes) of the schedules to claw back. All schedules by default.
+        schedule_ids: Option<Vec<u32>>,
+        /// Receives the clawed back tokens instead of the clawback recipient of the program
+        clawback_recipient: Option<String>,
+        /// Token to claw back, the token of the program when not set
+        token: Option<Token>,
+        /// Program of the vesting account, the default program when not set
+        program_id: Option<u64>,
     },
     Receive(Cw20ReceiveMsg),
+    /// Registers vesting accounts funded with the native token sent along
+    RegisterVestingAccounts {
+        vesting_accounts: Vec<VestingAccount>,
+        program_id: Option<u64>,
+    },
     ProposeNewOwner {
         owner: String,
         expires_in: u64,
     },
     DropOwnershipProposal {},
     ClaimOwnership {},
+    /// Creates a program with its own owner, which only the contract owner can do
+    CreateProgram {
+        owner: String,
+        token: Token,
+        clawback_recipient: Option<String>,
+        /// Default clawbackable flag for the schedules registered in the program, true when not set
+        clawbackable: Option<bool>,
+        /// Beneficiaries transfer their accounts alone when not set
+        transfer_policy: Option<TransferPolicy>,
+        /// Distributes the vested tokens of the program, along with the owner
+        operator: Option<String>,
+    },
+    /// Updates a program, which only its owner can do
+    UpdateProgram {
+        /// The default program when not set
+        program_id: Option<u64>,
+        owner: Option<String>,
+        clawback_recipient: Option<AddrUpdate>,
+        clawbackable: Option<bool>,
+        transfer_policy: Option<TransferPolicy>,
+        operator: Option<AddrUpdate>,
+    },
+    /// Allows a program to vest a token besides its default token, which only its owner can do
+    AllowToken {
+        token: Token,
+        program_id: Option<u64>,
+    },
+    /// Stops a program from registering vesting accounts in a token. Existing accounts keep
+    /// vesting.
+    DisallowToken {
+        token: Token,
+        program_id: Option<u64>,
+    },
+    /// Moves the vesting account of the sender to a new address, merging it into the account
+    /// already there. Only requests the transfer when the program requires the owner approval.
+    TransferVestingAccount {
+        new_address: String,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Completes the transfer requested by a beneficiary, which only the program owner can do
+    ApproveVestingAccountTransfer {
+        address: String,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Sets the recipient of the claims of the sender that do not select one, which is the
+    /// sender when not set
+    SetDefaultRecipient {
+        recipient: Option<String>,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Allows `claimer` to claim from the vesting account of the sender with `ClaimFor`
+    GrantClaimer {
+        claimer: String,
+        /// Never expires when not set
+        expires: Option<Expiration>,
+        /// Total amount the claimer can claim, unlimited when not set
+        max_amount: Option<Uint128>,
+        /// Receives the tokens claimed by the claimer instead of the beneficiary
+        recipient: Option<String>,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    RevokeClaimer {
+        claimer: String,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Claims on behalf of a beneficiary that granted the sender as claimer. The tokens go to the
+    /// recipient it approved for the claimer, or else to its default recipient or itself.
+    ClaimFor {
+        beneficiary: String,
+        amount: Option<Uint128>,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Sends their available amount to the listed beneficiaries, which only the program owner
+    /// or operator can do
+    Distribute {
+        addresses: Vec<String>,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Sends their available amount to a page of the beneficiaries of a program and token, in
+    /// ascending order of address
+    DistributeAll {
+        start_after: Option<String>,
+        limit: Option<u32>,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Sends tokens of the contract that are not owed to any beneficiary, which only the contract
+    /// owner can do. The token of the default program when not set.
+    WithdrawSurplus {
+        recipient: String,
+        amount: Uint128,
+        token: Option<Token>,
+    },
+    /// Cw721 transfer of the vesting account represented by the token. Only the owner of the token
+    /// can transfer it, the cw721 approval messages are not implemented.
+    TransferNft {
+        recipient: String,
+        token_id: String,
+    },
+    /// Cw721 transfer of the vesting account represented by the token to a contract, which is
+    /// notified with a `ReceiveNft` message
+    SendNft {
+        contract: String,
+        token_id: String,
+        msg: Binary,
+    },
 }
 
 #[cw_serde]
 pub enum Cw20HookMsg {
     RegisterVestingAccounts {
         vesting_accounts: Vec<VestingAccount>,
+        program_id: Option<u64>,
     },
 }
 
@@ -55,56 +235,226 @@ pub enum Cw20HookMsg {
 pub enum QueryMsg {
     #[returns(ConfigResponse)]
     Config {},
+    #[returns(ProgramResponse)]
+    Program { program_id: u64 },
+    #[returns(ProgramsResponse)]
+    Programs {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+        order_by: Option<OrderBy>,
+    },
+    /// Returns the tokens a program can vest, starting with its default token
+    #[returns(Vec<Token>)]
+    AllowedTokens { program_id: Option<u64> },
     #[returns(VestingAccountResponse)]
-    VestingAccount { address: String },
+    VestingAccount {
+        address: String,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Lists the vesting accounts of a program and token, the default program and its token
+    /// when not set
     #[returns(VestingAccountsResponse)]
     VestingAccounts {
         start_after: Option<String>,
         limit: Option<u32>,
         order_by: Option<OrderBy>,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    #[returns(Uint128)]
+    AvailableAmount {
+        address: String,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    #[returns(Option<ClaimerInfo>)]
+    Claimer {
+        beneficiary: String,
+        claimer: String,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Returns the new address of a transfer waiting for the owner approval
+    #[returns(Option<Addr>)]
+    PendingTransfer {
+        address: String,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Returns the unreleased amount (vested or not) of a vesting account at the start of a block,
+    /// the current one when not set. Adds up all programs when no program is selected.
+    #[returns(VotingPowerAtHeightResponse)]
+    VotingPowerAtHeight {
+        address: String,
+        height: Option<u64>,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Returns the total unreleased amount of the vesting accounts of a program and token at the
+    /// start of a block, the current one when not set. Adds up all programs when no program is
+    /// selected.
+    #[returns(TotalPowerAtHeightResponse)]
+    TotalPowerAtHeight {
+        height: Option<u64>,
+        token: Option<Token>,
+        program_id: Option<u64>,
     },
+    /// Returns the unreleased amount (vested or not) of a vesting account at the start of a block
     #[returns(Uint128)]
-    AvailableAmount { address: String },
+    UnreleasedAt {
+        address: String,
+        height: u64,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Returns the released amount of a vesting account at the start of a block
+    #[returns(Uint128)]
+    ReleasedAt {
+        address: String,
+        height: u64,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Returns the total unreleased amount of the vesting accounts of a program and token at the
+    /// start of a block
+    #[returns(Uint128)]
+    TotalUnreleasedAt {
+        height: u64,
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Returns the running totals of a program and token
+    #[returns(TotalsResponse)]
+    Totals {
+        token: Option<Token>,
+        program_id: Option<u64>,
+    },
+    /// Compares the balance of the contract in a token, the token of the default program when not
+    /// set, with the outstanding amount of all programs in that token
+    #[returns(InvariantsResponse)]
+    Invariants { token: Option<Token> },
     #[returns(u64)]
     Timestamp {},
+    #[returns(OwnerOfResponse)]
+    OwnerOf {
+        token_id: String,
+        /// Accepted for cw721 compatibility, the tokens have no approvals
+        include_expired: Option<bool>,
+    },
+    #[returns(NftInfoResponse)]
+    NftInfo { token_id: String },
+    #[returns(AllNftInfoResponse)]
+    AllNftInfo {
+        token_id: String,
+        include_expired: Option<bool>,
+    },
+    #[returns(TokensResponse)]
+    Tokens {
+        owner: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    #[returns(TokensResponse)]
+    AllTokens {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    #[returns(NumTokensResponse)]
+    NumTokens {},
+    #[returns(ContractInfoResponse)]
+    ContractInfo {},
 }
 
 #[cw_serde]
 pub struct VestingAccount {
     pub address: String,
     pub schedules: Vec<VestingSchedule>,
+    /// Default clawbackable flag for the schedules that do not set their own
     pub clawbackable: Option<bool>,
+    /// Mints a cw721 token representing the vesting account, unless it has one already
+    pub mint_nft: Option<bool>,
 }
 
 #[cw_serde]
 pub struct VestingSchedule {
     pub start_point: VestingSchedulePoint,
     pub end_point: Option<VestingSchedulePoint>,
+    /// Intermediate points of a piecewise linear curve between the start and end points, with
+    /// strictly increasing time and non-decreasing amount. Requires an end point.
+    pub points: Option<Vec<VestingSchedulePoint>>,
+    /// Nothing can be claimed before this time, in the unit of the schedule points. At the cliff,
+    /// the amount accrued since the start unlocks at once. Requires an end point.
+    pub cliff_time: Option<u64>,
+    /// Releases the tokens in equal steps at the end of every interval instead of continuously.
+    /// The interval must divide the time between the start and end points.
+    pub release_interval: Option<u64>,
+    /// Whether the owner can claw back the schedule, which is the case when not set
+    pub clawbackable: Option<bool>,
+    /// Time at which the schedule was terminated by a clawback, in the unit of the schedule points.
+    /// Nothing vests after this time, so a schedule terminated before its start time is cancelled.
+    pub terminated_at: Option<u64>,
 }
 
 #[cw_serde]
 pub struct VestingSchedulePoint {
     pub time: u64,
     pub amount: Uint128,
+    /// Unit of `time`, seconds by default. All points of a schedule must use the same unit.
+    pub unit: Option<TimeUnit>,
+}
+
+#[cw_serde]
+pub enum TimeUnit {
+    Seconds,
+    Height,
 }
 
 #[cw_serde]
 pub struct ConfigResponse {
     pub owner: Addr,
-    pub token_addr: Addr,
+}
+
+#[cw_serde]
+pub struct ProgramResponse {
+    pub program_id: u64,
+    pub owner: Addr,
+    pub token: Token,
+    pub clawback_recipient: Option<Addr>,
+    pub clawbackable: bool,
+    pub transfer_policy: TransferPolicy,
+    pub operator: Option<Addr>,
+}
+
+#[cw_serde]
+pub struct ProgramsResponse {
+    pub programs: Vec<ProgramResponse>,
 }
 
 #[cw_serde]
 pub struct VestingInfo {
     pub schedules: Vec<VestingSchedule>,
     pub released_amount: Uint128,
-    pub clawbackable: Option<bool>,
+}
+
+/// Permission to claim on behalf of a beneficiary
+#[cw_serde]
+pub struct ClaimerInfo {
+    pub expires: Expiration,
+    /// Amount the claimer can still claim, unlimited when not set
+    pub max_amount: Option<Uint128>,
+    /// Receives the claimed tokens instead of the beneficiary
+    pub recipient: Option<Addr>,
 }
 
 #[cw_serde]
 pub struct VestingAccountResponse {
     pub address: Addr,
+    pub program_id: u64,
+    pub token: Token,
     pub info: VestingInfo,
+    /// Receives the claims that do not select a recipient instead of the beneficiary
+    pub default_recipient: Option<Addr>,
 }
 
 #[cw_serde]
@@ -113,4 +463,119 @@ pub struct VestingAccountsResponse {
 }
 
 #[cw_serde]
-pub struct MigrateMsg {}
+pub struct TotalsResponse {
+    pub program_id: u64,
+    pub token: Token,
+    pub deposited: Uint128,
+    pub released: Uint128,
+    pub clawed_back: Uint128,
+    /// Deposited amount that is neither released nor clawed back, vested or not
+    pub outstanding: Uint128,
+}
+
+#[cw_serde]
+pub struct InvariantsResponse {
+    pub token: Token,
+    /// Balance of the contract in the token
+    pub balance: Uint128,
+    /// Amount owed to the beneficiaries of all programs, vested or not, summed from the vesting accounts
+    pub outstanding: Uint128,
+    /// Outstanding amount according to the deposited, released and clawed back totals
+    pub tracked_outstanding: Uint128,
+    /// Whether the totals disagree with the vesting accounts
+    pub totals_mismatch: bool,
+    /// Balance above the outstanding amount
+    pub surplus: Uint128,
+    /// Outstanding amount above the balance, which breaks the solvency of the contract
+    pub deficit: Uint128,
+}
+
+#[cw_serde]
+pub struct VotingPowerAtHeightResponse {
+    pub power: Uint128,
+    pub height: u64,
+}
+
+#[cw_serde]
+pub struct TotalPowerAtHeightResponse {
+    pub power: Uint128,
+    pub height: u64,
+}
+
+#[cw_serde]
+pub struct Approval {
+    pub spender: String,
+    pub expires: Expiration,
+}
+
+#[cw_serde]
+pub struct OwnerOfResponse {
+    pub owner: String,
+    /// Always empty, the tokens cannot be approved
+    pub approvals: Vec<Approval>,
+}
+
+/// Cw721 metadata of a token, which exposes the vesting account it represents
+#[cw_serde]
+pub struct NftInfoResponse {
+    pub token_uri: Option<String>,
+    pub extension: VestingAccountResponse,
+}
+
+#[cw_serde]
+pub struct AllNftInfoResponse {
+    pub access: OwnerOfResponse,
+    pub info: NftInfoResponse,
+}
+
+#[cw_serde]
+pub struct TokensResponse {
+    pub tokens: Vec<String>,
+}
+
+#[cw_serde]
+pub struct NumTokensResponse {
+    pub count: u64,
+}
+
+#[cw_serde]
+pub struct ContractInfoResponse {
+    pub name: String,
+    pub symbol: String,
+}
+
+/// Cw721 hook message sent to the contract receiving a token with `SendNft`
+#[cw_serde]
+pub struct Cw721ReceiveMsg {
+    pub sender: String,
+    pub token_id: String,
+    pub msg: Binary,
+}
+
+impl Cw721ReceiveMsg {
+    pub fn into_cosmos_msg<T: Into<String>>(self, contract_addr: T) -> StdResult<CosmosMsg> {
+        Ok(WasmMsg::Execute {
+            contract_addr: contract_addr.into(),
+            msg: to_json_binary(&Cw721ReceiverExecuteMsg::ReceiveNft(self))?,
+            funds: vec![],
+        }
+        .into())
+    }
+}
+
+#[cw_serde]
+enum Cw721ReceiverExecuteMsg {
+    ReceiveNft(Cw721ReceiveMsg),
+}
+
+#[cw_serde]
+pub struct MigrateMsg {
+    /// Accounts that were clawed back before schedules could be terminated
+    pub clawed_back_accounts: Option<Vec<ClawedBackAccount>>,
+}
+
+#[cw_serde]
+pub struct ClawedBackAccount {
+    pub address: String,
+    pub clawed_back_at: u64,
+}
@@ -1,13 +1,53 @@
 use cosmwasm_schema::cw_serde;
 
-use crate::msg::{OrderBy, VestingInfo};
-use cosmwasm_std::{Addr, Deps, StdResult};
-use cw_storage_plus::{Bound, Item, Map};
+use crate::msg::{ClaimerInfo, OrderBy, Token, TransferPolicy, VestingInfo};
+use cosmwasm_std::{Addr, Deps, Order, StdResult, Uint128};
+use cw_storage_plus::{
+    Bound, Index, IndexList, IndexedMap, Item, Map, MultiIndex, SnapshotMap, Strategy,
+};
 
 #[cw_serde]
 pub struct Config {
+    /// Creates the programs
     pub owner: Addr,
-    pub token_addr: Addr,
+}
+
+/// Pool of vesting accounts with its own owner and clawback policy
+#[cw_serde]
+pub struct Program {
+    /// Registers and claws back the vesting accounts of the program
+    pub owner: Addr,
+    /// Default token of the messages and queries that do not select one
+    pub token: Token,
+    /// Receives clawed back tokens instead of the owner
+    pub clawback_recipient: Option<Addr>,
+    /// Default clawbackable flag for the schedules registered in the program
+    pub clawbackable: bool,
+    pub transfer_policy: TransferPolicy,
+    /// Distributes the vested tokens of the program to the beneficiaries, along with the owner
+    pub operator: Option<Addr>,
+}
+
+/// Running totals of the vesting accounts of a program and token
+#[cw_serde]
+#[derive(Default)]
+pub struct Totals {
+    /// Registered in the schedules
+    pub deposited: Uint128,
+    /// Released to the beneficiaries by claims, distributions and clawbacks
+    pub released: Uint128,
+    /// Sent back to the clawback recipients
+    pub clawed_back: Uint128,
+}
+
+impl Totals {
+    /// Returns the deposited amount that is neither released nor clawed back, vested or not.
+    pub fn outstanding(&self) -> StdResult<Uint128> {
+        Ok(self
+            .deposited
+            .checked_sub(self.released)?
+            .checked_sub(self.clawed_back)?)
+    }
 }
 
 #[cw_serde]
@@ -18,7 +58,93 @@ pub struct OwnershipProposal {
 
 pub const CONFIG: Item<Config> = Item::new("config");
 
-pub const VESTING_INFO: Map<&Addr, VestingInfo> = Map::new("vesting_info");
+/// Id of the program created by the instantiation
+pub const DEFAULT_PROGRAM_ID: u64 = 0;
+
+pub const PROGRAMS: Map<u64, Program> = Map::new("programs");
+
+/// Tokens a program can vest besides its default token, by program id and token key
+pub const ALLOWED_TOKENS: Map<(u64, &str), Token> = Map::new("allowed_tokens");
+
+/// Id of the next program
+pub const PROGRAM_COUNT: Item<u64> = Item::new("program_count");
+
+/// Vesting info by program id, token key and beneficiary
+pub const VESTING_INFO: Map<(u64, &str, &Addr), VestingInfo> = Map::new("program_vesting_info");
+
+/// Unreleased amount (vested or not) of the vesting accounts by height, with the same keys as the
+/// vesting infos
+pub const UNRELEASED_AMOUNTS: SnapshotMap<(u64, &str, &Addr), Uint128> = SnapshotMap::new(
+    "unreleased_amounts",
+    "unreleased_amounts__checkpoints",
+    "unreleased_amounts__changelog",
+    Strategy::EveryBlock,
+);
+
+/// Released amount of the vesting accounts by height, with the same keys as the vesting infos
+pub const RELEASED_AMOUNTS: SnapshotMap<(u64, &str, &Addr), Uint128> = SnapshotMap::new(
+    "released_amounts",
+    "released_amounts__checkpoints",
+    "released_amounts__changelog",
+    Strategy::EveryBlock,
+);
+
+/// Total unreleased amount of the vesting accounts by program id and token key, by height
+pub const TOTAL_UNRELEASED_AMOUNTS: SnapshotMap<(u64, &str), Uint128> = SnapshotMap::new(
+    "total_unreleased_amounts",
+    "total_unreleased_amounts__checkpoints",
+    "total_unreleased_amounts__changelog",
+    Strategy::EveryBlock,
+);
+
+/// Running totals by token key and program id, so the totals of a token can be read across programs
+pub const TOTALS: Map<(&str, u64), Totals> = Map::new("totals");
+
+/// New address of the vesting accounts waiting for the owner approval to be transferred,
+/// with the same keys as the vesting infos
+pub const PENDING_TRANSFERS: Map<(u64, &str, &Addr), Addr> = Map::new("pending_transfers");
+
+/// Default recipient of the claims of the vesting accounts, with the same keys as the vesting infos
+pub const DEFAULT_RECIPIENTS: Map<(u64, &str, &Addr), Addr> = Map::new("default_recipients");
+
+/// Claimers by program id and token key, beneficiary and claimer
+pub const CLAIMERS: Map<((u64, &str), &Addr, &Addr), ClaimerInfo> = Map::new("claimers");
+
+/// Vesting account represented by a cw721 token, whose owner is the beneficiary
+#[cw_serde]
+pub struct Nft {
+    pub owner: Addr,
+    pub program_id: u64,
+    pub token: Token,
+}
+
+pub struct NftIndexes<'a> {
+    pub owner: MultiIndex<'a, Addr, Nft, String>,
+}
+
+impl<'a> IndexList<Nft> for NftIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Nft>> + '_> {
+        let v: Vec<&dyn Index<Nft>> = vec![&self.owner];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Cw721 tokens by token id
+pub fn nfts<'a>() -> IndexedMap<'a, &'a str, Nft, NftIndexes<'a>> {
+    IndexedMap::new(
+        "nfts",
+        NftIndexes {
+            owner: MultiIndex::new(|_pk, nft| nft.owner.clone(), "nfts", "nfts__owner"),
+        },
+    )
+}
+
+/// Number of minted cw721 tokens, which is also the id of the next one
+pub const NFT_COUNT: Item<u64> = Item::new("nft_count");
+
+/// Cw721 token id of the vesting accounts represented by one, with the same keys as the
+/// vesting infos
+pub const NFT_IDS: Map<(u64, &str, &Addr), String> = Map::new("nft_ids");
 
 pub const OWNERSHIP_PROPOSAL: Item<OwnershipProposal> = Item::new("ownership_proposal");
 
@@ -27,6 +153,8 @@ const DEFAULT_LIMIT: u32 = 10;
 
 pub fn read_vesting_infos(
     deps: Deps,
+    program_id: u64,
+    token: &Token,
     start_after: Option<Addr>,
     limit: Option<u32>,
     order_by: Option<OrderBy>,
@@ -40,6 +168,7 @@ pub fn read_vesting_infos(
     };
 
     let info: Vec<(Addr, VestingInfo)> = VESTING_INFO
+        .prefix((program_id, token.key()))
         .range(
             deps.storage,
             start,
@@ -53,6 +182,88 @@ pub fn read_vesting_infos(
     Ok(info)
 }
 
+pub fn read_programs(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+    order_by: Option<OrderBy>,
+) -> StdResult<Vec<(u64, Program)>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after.map(Bound::exclusive);
+
+    let (start, end) = match &order_by {
+        Some(OrderBy::Desc) => (None, start_after),
+        _ => (start_after, None),
+    };
+
+    PROGRAMS
+        .range(
+            deps.storage,
+            start,
+            end,
+            order_by.unwrap_or(OrderBy::Asc).into(),
+        )
+        .take(limit)
+        .collect()
+}
+
+/// Returns the unreleased amount of the vesting accounts in a token across all programs.
+pub fn read_outstanding_amount(deps: Deps, token_key: &str) -> StdResult<Uint128> {
+    PROGRAMS
+        .keys(deps.storage, None, None, Order::Ascending)
+        .try_fold(Uint128::zero(), |outstanding, program_id| {
+            let unreleased = TOTAL_UNRELEASED_AMOUNTS
+                .may_load(deps.storage, (program_id?, token_key))?
+                .unwrap_or_default();
+            Ok(outstanding.checked_add(unreleased)?)
+        })
+}
+
+/// Returns the outstanding amount of a token across all programs according to the totals.
+pub fn read_tracked_outstanding_amount(deps: Deps, token_key: &str) -> StdResult<Uint128> {
+    TOTALS
+        .prefix(token_key)
+        .range(deps.storage, None, None, Order::Ascending)
+        .try_fold(Uint128::zero(), |outstanding, item| {
+            let (_, totals) = item?;
+            Ok(outstanding.checked_add(totals.outstanding()?)?)
+        })
+}
+
+/// Lists the cw721 token ids in ascending order, only the ones of `owner` when set.
+pub fn read_nft_ids(
+    deps: Deps,
+    owner: Option<Addr>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<Vec<String>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    match owner {
+        Some(owner) => nfts()
+            .idx
+            .owner
+            .prefix(owner)
+            .keys(
+                deps.storage,
+                start_after.map(Bound::exclusive),
+                None,
+                Order::Ascending,
+            )
+            .take(limit)
+            .collect(),
+        None => nfts()
+            .keys(
+                deps.storage,
+                start_after.as_deref().map(Bound::exclusive),
+                None,
+                Order::Ascending,
+            )
+            .take(limit)
+            .collect(),
+    }
+}
+
 #[cfg(test)]
 mod testing {
     use super::*;
@@ -66,19 +277,28 @@ mod testing {
         let vi_mock = VestingInfo {
             released_amount: Uint128::zero(),
             schedules: vec![],
-            clawbackable: None,
         };
 
+        let token = Token::Native("token".to_string());
+
         for i in 1..5 {
             let key = Addr::unchecked(format! {"address{}", i});
 
             VESTING_INFO
-                .save(&mut deps.storage, &key, &vi_mock)
+                .save(&mut deps.storage, (0, token.key(), &key), &vi_mock)
+                .unwrap();
+            VESTING_INFO
+                .save(&mut deps.storage, (0, "other_token", &key), &vi_mock)
+                .unwrap();
+            VESTING_INFO
+                .save(&mut deps.storage, (1, token.key(), &key), &vi_mock)
                 .unwrap();
         }
 
         let res = read_vesting_infos(
             deps.as_ref(),
+            0,
+            &token,
             Some(Addr::unchecked("address2")),
             None,
             Some(OrderBy::Asc),
@@ -94,6 +314,8 @@ mod testing {
 
         let res = read_vesting_infos(
             deps.as_ref(),
+            0,
+            &token,
             Some(Addr::unchecked("address2")),
             Some(1),
             Some(OrderBy::Asc),
@@ -103,6 +325,8 @@ mod testing {
 
         let res = read_vesting_infos(
             deps.as_ref(),
+            0,
+            &token,
             Some(Addr::unchecked("address3")),
             None,
             Some(OrderBy::Desc),
@@ -118,6 +342,8 @@ mod testing {
 
         let res = read_vesting_infos(
             deps.as_ref(),
+            0,
+            &token,
             Some(Addr::unchecked("address3")),
             Some(1),
             Some(OrderBy::Desc),
@@ -1,8 +1,154 @@
-use crate::contract::{instantiate, query};
+use crate::contract::{execute, instantiate, migrate, query};
 
-use crate::msg::{ConfigResponse, InstantiateMsg, QueryMsg};
-use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-use cosmwasm_std::{from_binary, Addr};
+use crate::error::ContractError;
+use crate::migration::{ConfigV100, VestingInfoV100, CONFIG_V100, VESTING_INFO_V100};
+use crate::msg::{
+    AddrUpdate, ClaimerInfo, ClawedBackAccount, ConfigResponse, Cw20HookMsg, ExecuteMsg,
+    InstantiateMsg, InvariantsResponse, MigrateMsg, NftInfoResponse, OwnerOfResponse,
+    ProgramResponse, ProgramsResponse, QueryMsg, TimeUnit, Token, TokensResponse,
+    TotalPowerAtHeightResponse, TotalsResponse, TransferPolicy, VestingAccount,
+    VestingAccountResponse, VestingAccountsResponse, VestingSchedule, VestingSchedulePoint,
+    VotingPowerAtHeightResponse,
+};
+use crate::state::{Totals, CONFIG, PROGRAMS, TOTALS, UNRELEASED_AMOUNTS, VESTING_INFO};
+use cosmwasm_std::testing::{
+    mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR,
+};
+use cosmwasm_std::{
+    attr, coins, from_json, to_json_binary, Addr, BankMsg, ContractResult, Env, OwnedDeps,
+    StdError, SubMsg, SystemResult, Timestamp, Uint128, WasmMsg, WasmQuery,
+};
+use cw20::{BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg, Cw20ReceiveMsg};
+use cw_utils::{Expiration, PaymentError};
+
+type MockDeps = OwnedDeps<MockStorage, MockApi, MockQuerier>;
+
+fn setup() -> (MockDeps, Env) {
+    let mut deps = mock_dependencies();
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr1234", &[]),
+        InstantiateMsg {
+            owner: "owner".to_string(),
+            token: Token::Cw20(Addr::unchecked("vested_token")),
+            clawback_recipient: None,
+        },
+    )
+    .unwrap();
+
+    (deps, env)
+}
+
+fn linear_schedule(start: u64, end: u64, amount: u128) -> VestingSchedule {
+    VestingSchedule {
+        start_point: VestingSchedulePoint {
+            time: start,
+            amount: Uint128::zero(),
+            unit: None,
+        },
+        end_point: Some(VestingSchedulePoint {
+            time: end,
+            amount: Uint128::new(amount),
+            unit: None,
+        }),
+        points: None,
+        cliff_time: None,
+        release_interval: None,
+        clawbackable: None,
+        terminated_at: None,
+    }
+}
+
+fn register(deps: &mut MockDeps, env: &Env, vesting_accounts: Vec<VestingAccount>, amount: u128) {
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("vested_token", &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "owner".to_string(),
+            amount: Uint128::new(amount),
+            msg: to_json_binary(&Cw20HookMsg::RegisterVestingAccounts {
+                vesting_accounts,
+                program_id: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap();
+}
+
+fn query_vesting_account(deps: &MockDeps, env: &Env, address: &str) -> VestingAccountResponse {
+    from_json(
+        query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::VestingAccount {
+                address: address.to_string(),
+                token: None,
+                program_id: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap()
+}
+
+fn query_available_amount(deps: &MockDeps, env: &Env, address: &str) -> Uint128 {
+    from_json(
+        query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::AvailableAmount {
+                address: address.to_string(),
+                token: None,
+                program_id: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap()
+}
+
+fn cw20_transfer(recipient: &str, amount: u128) -> SubMsg {
+    SubMsg::new(WasmMsg::Execute {
+        contract_addr: "vested_token".to_string(),
+        funds: vec![],
+        msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+            recipient: recipient.to_string(),
+            amount: Uint128::new(amount),
+        })
+        .unwrap(),
+    })
+}
+
+fn query_invariants(deps: &MockDeps, env: &Env, token: Option<Token>) -> InvariantsResponse {
+    from_json(query(deps.as_ref(), env.clone(), QueryMsg::Invariants { token }).unwrap()).unwrap()
+}
+
+fn mock_cw20_balance(deps: &mut MockDeps, balance: u128) {
+    deps.querier.update_wasm(move |query| match query {
+        WasmQuery::Smart { contract_addr, msg } if contract_addr == "vested_token" => {
+            let msg: Cw20QueryMsg = from_json(msg).unwrap();
+            assert_eq!(
+                msg,
+                Cw20QueryMsg::Balance {
+                    address: MOCK_CONTRACT_ADDR.to_string()
+                }
+            );
+            SystemResult::Ok(ContractResult::Ok(
+                to_json_binary(&BalanceResponse {
+                    balance: Uint128::new(balance),
+                })
+                .unwrap(),
+            ))
+        }
+        _ => panic!("unexpected query"),
+    });
+}
 
 #[test]
 fn proper_initialization() {
@@ -10,19 +156,3148 @@ fn proper_initialization() {
 
     let msg = InstantiateMsg {
         owner: "owner".to_string(),
-        token_addr: "vested_token".to_string(),
+        token: Token::Cw20(Addr::unchecked("vested_token")),
+        clawback_recipient: None,
     };
 
     let env = mock_env();
-    let info = mock_info("addr1234", &vec![]);
+    let info = mock_info("addr1234", &[]);
     let _res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
 
     assert_eq!(
-        from_binary::<ConfigResponse>(&query(deps.as_ref(), env, QueryMsg::Config {}).unwrap())
-            .unwrap(),
+        from_json::<ConfigResponse>(
+            &query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap()
+        )
+        .unwrap(),
         ConfigResponse {
             owner: Addr::unchecked("owner"),
-            token_addr: Addr::unchecked("vested_token"),
         }
     );
+    assert_eq!(
+        from_json::<ProgramResponse>(
+            &query(deps.as_ref(), env, QueryMsg::Program { program_id: 0 }).unwrap()
+        )
+        .unwrap(),
+        ProgramResponse {
+            program_id: 0,
+            owner: Addr::unchecked("owner"),
+            token: Token::Cw20(Addr::unchecked("vested_token")),
+            clawback_recipient: None,
+            clawbackable: true,
+            transfer_policy: TransferPolicy::Beneficiary,
+            operator: None,
+        }
+    );
+}
+
+#[test]
+fn cliff_unlocks_accrued_amount_at_once() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![VestingSchedule {
+                cliff_time: Some(200),
+                ..linear_schedule(100, 500, 400)
+            }],
+            clawbackable: Some(true),
+            mint_nft: None,
+        }],
+        400,
+    );
+
+    for (time, available) in [(150, 0), (199, 0), (200, 100), (300, 200), (600, 400)] {
+        env.block.time = Timestamp::from_seconds(time);
+        assert_eq!(
+            query_available_amount(&deps, &env, "beneficiary"),
+            Uint128::new(available)
+        );
+    }
+
+    env.block.time = Timestamp::from_seconds(150);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary"),
+            amount: None,
+            schedule_ids: None,
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![cw20_transfer("owner", 400)]);
+
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("vested_token", &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "owner".to_string(),
+            amount: Uint128::new(400),
+            msg: to_json_binary(&Cw20HookMsg::RegisterVestingAccounts {
+                vesting_accounts: vec![VestingAccount {
+                    address: "beneficiary".to_string(),
+                    schedules: vec![VestingSchedule {
+                        cliff_time: Some(600),
+                        ..linear_schedule(100, 500, 400)
+                    }],
+                    clawbackable: None,
+                    mint_nft: None,
+                }],
+                program_id: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::VestingScheduleError("beneficiary".to_string())
+    );
+}
+
+#[test]
+fn periodic_schedule_releases_at_interval_boundaries() {
+    let (mut deps, mut env) = setup();
+
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("vested_token", &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "owner".to_string(),
+            amount: Uint128::new(300),
+            msg: to_json_binary(&Cw20HookMsg::RegisterVestingAccounts {
+                vesting_accounts: vec![VestingAccount {
+                    address: "beneficiary".to_string(),
+                    schedules: vec![VestingSchedule {
+                        release_interval: Some(70),
+                        ..linear_schedule(100, 400, 300)
+                    }],
+                    clawbackable: None,
+                    mint_nft: None,
+                }],
+                program_id: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::VestingScheduleIntervalError("beneficiary".to_string())
+    );
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![VestingSchedule {
+                release_interval: Some(100),
+                ..linear_schedule(100, 400, 300)
+            }],
+            clawbackable: Some(true),
+            mint_nft: None,
+        }],
+        300,
+    );
+
+    for (time, available) in [
+        (99, 0),
+        (100, 0),
+        (199, 0),
+        (200, 100),
+        (299, 100),
+        (300, 200),
+        (399, 200),
+        (400, 300),
+        (500, 300),
+    ] {
+        env.block.time = Timestamp::from_seconds(time);
+        assert_eq!(
+            query_available_amount(&deps, &env, "beneficiary"),
+            Uint128::new(available)
+        );
+    }
+
+    env.block.time = Timestamp::from_seconds(250);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary"),
+            amount: Some(Uint128::new(100)),
+            schedule_ids: None,
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+
+    let account = query_vesting_account(&deps, &env, "beneficiary");
+    assert_eq!(
+        account.info.schedules[0].start_point,
+        VestingSchedulePoint {
+            time: 200,
+            amount: Uint128::new(100),
+            unit: None,
+        }
+    );
+
+    for (time, available) in [(299, 0), (300, 50), (400, 100)] {
+        env.block.time = Timestamp::from_seconds(time);
+        assert_eq!(
+            query_available_amount(&deps, &env, "beneficiary"),
+            Uint128::new(available)
+        );
+    }
+}
+
+#[test]
+fn piecewise_linear_schedule() {
+    let (mut deps, mut env) = setup();
+
+    let point = |time: u64, amount: u128| VestingSchedulePoint {
+        time,
+        amount: Uint128::new(amount),
+        unit: None,
+    };
+
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("vested_token", &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "owner".to_string(),
+            amount: Uint128::new(1000),
+            msg: to_json_binary(&Cw20HookMsg::RegisterVestingAccounts {
+                vesting_accounts: vec![VestingAccount {
+                    address: "beneficiary".to_string(),
+                    schedules: vec![VestingSchedule {
+                        points: Some(vec![point(200, 600), point(150, 700)]),
+                        ..linear_schedule(100, 400, 1000)
+                    }],
+                    clawbackable: None,
+                    mint_nft: None,
+                }],
+                program_id: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::VestingSchedulePointsError("beneficiary".to_string())
+    );
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![VestingSchedule {
+                points: Some(vec![point(200, 600)]),
+                ..linear_schedule(100, 400, 1000)
+            }],
+            clawbackable: Some(true),
+            mint_nft: None,
+        }],
+        1000,
+    );
+
+    for (time, available) in [(150, 300), (200, 600), (300, 800), (400, 1000)] {
+        env.block.time = Timestamp::from_seconds(time);
+        assert_eq!(
+            query_available_amount(&deps, &env, "beneficiary"),
+            Uint128::new(available)
+        );
+    }
+
+    env.block.time = Timestamp::from_seconds(150);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary"),
+            amount: Some(Uint128::new(350)),
+            schedule_ids: None,
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+
+    let account = query_vesting_account(&deps, &env, "beneficiary");
+    assert_eq!(account.info.schedules[0].start_point, point(150, 300));
+    assert_eq!(
+        account.info.schedules[0].points,
+        Some(vec![point(200, 450)])
+    );
+    assert_eq!(account.info.schedules[0].end_point, Some(point(400, 650)));
+}
+
+#[test]
+fn height_based_schedule() {
+    let (mut deps, mut env) = setup();
+    env.block.height = 1000;
+
+    let height_point = |height: u64, amount: u128| VestingSchedulePoint {
+        time: height,
+        amount: Uint128::new(amount),
+        unit: Some(TimeUnit::Height),
+    };
+
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("vested_token", &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "owner".to_string(),
+            amount: Uint128::new(1000),
+            msg: to_json_binary(&Cw20HookMsg::RegisterVestingAccounts {
+                vesting_accounts: vec![VestingAccount {
+                    address: "beneficiary".to_string(),
+                    schedules: vec![VestingSchedule {
+                        start_point: height_point(1000, 0),
+                        ..linear_schedule(1000, 2000, 1000)
+                    }],
+                    clawbackable: None,
+                    mint_nft: None,
+                }],
+                program_id: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::VestingScheduleUnitError("beneficiary".to_string())
+    );
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![
+                VestingSchedule {
+                    start_point: height_point(1000, 0),
+                    end_point: Some(height_point(2000, 1000)),
+                    ..linear_schedule(0, 0, 0)
+                },
+                linear_schedule(100, 200, 500),
+            ],
+            clawbackable: Some(true),
+            mint_nft: None,
+        }],
+        1500,
+    );
+
+    env.block.height = 1500;
+    env.block.time = Timestamp::from_seconds(150);
+    assert_eq!(
+        query_available_amount(&deps, &env, "beneficiary"),
+        Uint128::new(750)
+    );
+
+    env.block.height = 1250;
+    env.block.time = Timestamp::from_seconds(200);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary"),
+            amount: None,
+            schedule_ids: None,
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+
+    let account = query_vesting_account(&deps, &env, "beneficiary");
+    assert_eq!(account.info.schedules[0].terminated_at, Some(1250));
+    assert_eq!(account.info.schedules[1].terminated_at, Some(200));
+}
+
+#[test]
+fn clawback_splits_vested_and_unvested_amounts() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 1000)],
+            clawbackable: Some(true),
+            mint_nft: None,
+        }],
+        1000,
+    );
+
+    env.block.time = Timestamp::from_seconds(125);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        ExecuteMsg::Claim {
+            recipient: None,
+            splits: None,
+            amount: Some(Uint128::new(100)),
+            token: None,
+            program_id: None,
+            msg: None,
+        },
+    )
+    .unwrap();
+
+    env.block.time = Timestamp::from_seconds(160);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary"),
+            amount: None,
+            schedule_ids: None,
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        res.messages,
+        vec![
+            cw20_transfer("beneficiary", 500),
+            cw20_transfer("owner", 400)
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "clawback"),
+            attr("address", "beneficiary"),
+            attr("program_id", "0"),
+            attr("token", "vested_token"),
+            attr("clawback_recipient", "owner"),
+            attr("vested_amount", "500"),
+            attr("clawback_amount", "400"),
+        ]
+    );
+
+    assert_eq!(
+        query_available_amount(&deps, &env, "beneficiary"),
+        Uint128::zero()
+    );
+
+    let account = query_vesting_account(&deps, &env, "beneficiary");
+    assert_eq!(account.info.schedules[0].terminated_at, Some(160));
+    assert_eq!(account.info.released_amount, Uint128::new(600));
+}
+
+#[test]
+fn clawback_cancels_schedules_that_have_not_started() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![
+                linear_schedule(100, 200, 1000),
+                linear_schedule(300, 400, 500),
+            ],
+            clawbackable: Some(true),
+            mint_nft: None,
+        }],
+        1500,
+    );
+
+    env.block.time = Timestamp::from_seconds(200);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary"),
+            amount: None,
+            schedule_ids: None,
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        res.messages,
+        vec![
+            cw20_transfer("beneficiary", 1000),
+            cw20_transfer("owner", 500)
+        ]
+    );
+
+    env.block.time = Timestamp::from_seconds(400);
+    assert_eq!(
+        query_available_amount(&deps, &env, "beneficiary"),
+        Uint128::zero()
+    );
+    let account = query_vesting_account(&deps, &env, "beneficiary");
+    assert_eq!(account.info.schedules[1].terminated_at, Some(200));
+}
+
+#[test]
+fn partial_clawback_keeps_the_rest_vesting() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![
+                linear_schedule(100, 200, 1000),
+                linear_schedule(300, 400, 500),
+            ],
+            clawbackable: Some(true),
+            mint_nft: None,
+        }],
+        1500,
+    );
+
+    env.block.time = Timestamp::from_seconds(150);
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary"),
+            amount: None,
+            schedule_ids: Some(vec![2]),
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::VestingScheduleNotFound(2));
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary"),
+            amount: Some(Uint128::new(700)),
+            schedule_ids: None,
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            cw20_transfer("beneficiary", 500),
+            cw20_transfer("owner", 700)
+        ]
+    );
+
+    let account = query_vesting_account(&deps, &env, "beneficiary");
+    assert_eq!(
+        account.info.schedules[0].start_point,
+        VestingSchedulePoint {
+            time: 150,
+            amount: Uint128::new(500),
+            unit: None,
+        }
+    );
+    assert_eq!(account.info.schedules[1].terminated_at, Some(150));
+
+    env.block.time = Timestamp::from_seconds(175);
+    assert_eq!(
+        query_available_amount(&deps, &env, "beneficiary"),
+        Uint128::new(150)
+    );
+    env.block.time = Timestamp::from_seconds(400);
+    assert_eq!(
+        query_available_amount(&deps, &env, "beneficiary"),
+        Uint128::new(300)
+    );
+}
+
+#[test]
+fn partial_clawback_only_pays_out_the_touched_schedules() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![
+                linear_schedule(100, 200, 1000),
+                linear_schedule(300, 400, 500),
+            ],
+            clawbackable: Some(true),
+            mint_nft: None,
+        }],
+        1500,
+    );
+
+    // The amount is taken from the schedule that has not started, the vested part of the first
+    // schedule is left to be claimed
+    env.block.time = Timestamp::from_seconds(150);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary"),
+            amount: Some(Uint128::new(100)),
+            schedule_ids: None,
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![cw20_transfer("owner", 100)]);
+
+    let account = query_vesting_account(&deps, &env, "beneficiary");
+    assert_eq!(account.info.released_amount, Uint128::zero());
+    assert_eq!(account.info.schedules[0].terminated_at, None);
+    assert_eq!(
+        query_available_amount(&deps, &env, "beneficiary"),
+        Uint128::new(500)
+    );
+}
+
+#[test]
+fn clawback_by_schedule_id() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 1000)],
+            clawbackable: Some(true),
+            mint_nft: None,
+        }],
+        1000,
+    );
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 500)],
+            clawbackable: Some(true),
+            mint_nft: None,
+        }],
+        500,
+    );
+
+    env.block.time = Timestamp::from_seconds(150);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary"),
+            amount: None,
+            schedule_ids: Some(vec![1]),
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            cw20_transfer("beneficiary", 250),
+            cw20_transfer("owner", 250)
+        ]
+    );
+
+    env.block.time = Timestamp::from_seconds(200);
+    assert_eq!(
+        query_available_amount(&deps, &env, "beneficiary"),
+        Uint128::new(1000)
+    );
+}
+
+#[test]
+fn clawback_only_takes_clawbackable_schedules() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 1000)],
+            clawbackable: Some(true),
+            mint_nft: None,
+        }],
+        1000,
+    );
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 500)],
+            clawbackable: Some(false),
+            mint_nft: None,
+        }],
+        500,
+    );
+
+    env.block.time = Timestamp::from_seconds(150);
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary"),
+            amount: None,
+            schedule_ids: Some(vec![1]),
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary"),
+            amount: None,
+            schedule_ids: None,
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            cw20_transfer("beneficiary", 500),
+            cw20_transfer("owner", 500)
+        ]
+    );
+
+    let account = query_vesting_account(&deps, &env, "beneficiary");
+    assert_eq!(account.info.schedules[0].terminated_at, Some(150));
+    assert_eq!(account.info.schedules[1].terminated_at, None);
+    assert_eq!(account.info.released_amount, Uint128::new(500));
+
+    // The vested part of the non-clawbackable schedule is still claimable
+    assert_eq!(
+        query_available_amount(&deps, &env, "beneficiary"),
+        Uint128::new(250)
+    );
+
+    // Terminated schedules cannot be clawed back again to force out that amount
+    for schedule_ids in [None, Some(vec![0])] {
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("owner", &[]),
+            ExecuteMsg::Clawback {
+                recipient: Addr::unchecked("beneficiary"),
+                amount: None,
+                schedule_ids,
+                clawback_recipient: None,
+                token: None,
+                program_id: None,
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NothingToClawBack {});
+    }
+    env.block.time = Timestamp::from_seconds(200);
+    assert_eq!(
+        query_available_amount(&deps, &env, "beneficiary"),
+        Uint128::new(500)
+    );
+}
+
+#[test]
+fn clawback_to_configured_recipient() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![
+            VestingAccount {
+                address: "beneficiary1".to_string(),
+                schedules: vec![linear_schedule(100, 200, 1000)],
+                clawbackable: Some(true),
+                mint_nft: None,
+            },
+            VestingAccount {
+                address: "beneficiary2".to_string(),
+                schedules: vec![linear_schedule(100, 200, 1000)],
+                clawbackable: Some(true),
+                mint_nft: None,
+            },
+        ],
+        2000,
+    );
+
+    let update_config = ExecuteMsg::UpdateProgram {
+        program_id: None,
+        owner: None,
+        clawback_recipient: Some(AddrUpdate::Set("treasury".to_string())),
+        clawbackable: None,
+        transfer_policy: None,
+        operator: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary1", &[]),
+        update_config.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        update_config,
+    )
+    .unwrap();
+
+    env.block.time = Timestamp::from_seconds(150);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary1"),
+            amount: None,
+            schedule_ids: None,
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(res.messages[1], cw20_transfer("treasury", 500));
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary2"),
+            amount: None,
+            schedule_ids: None,
+            clawback_recipient: Some("other_treasury".to_string()),
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(res.messages[1], cw20_transfer("other_treasury", 500));
+}
+
+#[test]
+fn update_program_clears_optional_addresses() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 1000)],
+            clawbackable: Some(true),
+            mint_nft: None,
+        }],
+        1000,
+    );
+
+    let update_program =
+        |clawback_recipient: AddrUpdate, operator: AddrUpdate| ExecuteMsg::UpdateProgram {
+            program_id: None,
+            owner: None,
+            clawback_recipient: Some(clawback_recipient),
+            clawbackable: None,
+            transfer_policy: None,
+            operator: Some(operator),
+        };
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        update_program(
+            AddrUpdate::Set("treasury".to_string()),
+            AddrUpdate::Set("operator".to_string()),
+        ),
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        update_program(AddrUpdate::Clear {}, AddrUpdate::Clear {}),
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes[2..],
+        vec![attr("clawback_recipient", "none"), attr("operator", "none")]
+    );
+
+    let program: ProgramResponse = from_json(
+        query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Program { program_id: 0 },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(program.clawback_recipient, None);
+    assert_eq!(program.operator, None);
+
+    // The revoked operator cannot distribute anymore
+    env.block.time = Timestamp::from_seconds(150);
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("operator", &[]),
+        ExecuteMsg::Distribute {
+            addresses: vec!["beneficiary".to_string()],
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    // Clawed back tokens go to the owner again
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary"),
+            amount: None,
+            schedule_ids: None,
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(res.messages[1], cw20_transfer("owner", 500));
+}
+
+#[test]
+fn native_token_vesting() {
+    let mut deps = mock_dependencies();
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr1234", &[]),
+        InstantiateMsg {
+            owner: "owner".to_string(),
+            token: Token::Native("ustake".to_string()),
+            clawback_recipient: None,
+        },
+    )
+    .unwrap();
+
+    let register_msg = ExecuteMsg::RegisterVestingAccounts {
+        vesting_accounts: vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 1000)],
+            clawbackable: Some(true),
+            mint_nft: None,
+        }],
+        program_id: None,
+    };
+
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        register_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Payment(PaymentError::NoFunds {}));
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &coins(1000, "ustake")),
+        register_msg,
+    )
+    .unwrap();
+
+    env.block.time = Timestamp::from_seconds(150);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        ExecuteMsg::Claim {
+            recipient: None,
+            splits: None,
+            amount: None,
+            token: None,
+            program_id: None,
+            msg: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "beneficiary".to_string(),
+            amount: coins(500, "ustake"),
+        })]
+    );
+    env.block.time = Timestamp::from_seconds(200);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        ExecuteMsg::Claim {
+            recipient: Some("staking".to_string()),
+            splits: None,
+            amount: None,
+            token: None,
+            program_id: None,
+            msg: Some(to_json_binary("stake").unwrap()),
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(WasmMsg::Execute {
+            contract_addr: "staking".to_string(),
+            funds: coins(500, "ustake"),
+            msg: to_json_binary("stake").unwrap(),
+        })]
+    );
+}
+
+#[test]
+fn vesting_several_tokens() {
+    let (mut deps, mut env) = setup();
+
+    let vesting_accounts = vec![VestingAccount {
+        address: "beneficiary".to_string(),
+        schedules: vec![linear_schedule(100, 200, 1000)],
+        clawbackable: None,
+        mint_nft: None,
+    }];
+    register(&mut deps, &env, vesting_accounts.clone(), 1000);
+
+    let register_native = ExecuteMsg::RegisterVestingAccounts {
+        vesting_accounts: vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 2000)],
+            clawbackable: None,
+            mint_nft: None,
+        }],
+        program_id: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &coins(2000, "ustake")),
+        register_native.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::InvalidToken {});
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::AllowToken {
+            token: Token::Native("ustake".to_string()),
+            program_id: None,
+        },
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &coins(2000, "ustake")),
+        register_native,
+    )
+    .unwrap();
+
+    env.block.time = Timestamp::from_seconds(150);
+    let native_token = Token::Native("ustake".to_string());
+    let available: Uint128 = from_json(
+        query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::AvailableAmount {
+                address: "beneficiary".to_string(),
+                token: Some(native_token.clone()),
+                program_id: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(available, Uint128::new(1000));
+    assert_eq!(
+        query_available_amount(&deps, &env, "beneficiary"),
+        Uint128::new(500)
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        ExecuteMsg::Claim {
+            recipient: None,
+            splits: None,
+            amount: None,
+            token: Some(native_token.clone()),
+            program_id: None,
+            msg: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "beneficiary".to_string(),
+            amount: coins(1000, "ustake"),
+        })]
+    );
+    assert_eq!(
+        query_available_amount(&deps, &env, "beneficiary"),
+        Uint128::new(500)
+    );
+
+    let res: VestingAccountsResponse = from_json(
+        query(
+            deps.as_ref(),
+            env,
+            QueryMsg::VestingAccounts {
+                start_after: None,
+                limit: None,
+                order_by: None,
+                token: Some(native_token.clone()),
+                program_id: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.vesting_accounts.len(), 1);
+    assert_eq!(res.vesting_accounts[0].token, native_token);
+    assert_eq!(
+        res.vesting_accounts[0].info.released_amount,
+        Uint128::new(1000)
+    );
+}
+
+#[test]
+fn register_only_allowed_cw20_tokens() {
+    let (mut deps, env) = setup();
+
+    // Any contract can pose as the program owner in the hook message
+    let register_from = |deps: &mut MockDeps, token: &str| {
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(token, &[]),
+            ExecuteMsg::Receive(Cw20ReceiveMsg {
+                sender: "owner".to_string(),
+                amount: Uint128::new(1000),
+                msg: to_json_binary(&Cw20HookMsg::RegisterVestingAccounts {
+                    vesting_accounts: vec![VestingAccount {
+                        address: "victim".to_string(),
+                        schedules: vec![linear_schedule(100, 200, 1000)],
+                        clawbackable: None,
+                        mint_nft: Some(true),
+                    }],
+                    program_id: None,
+                })
+                .unwrap(),
+            }),
+        )
+    };
+
+    let err = register_from(&mut deps, "evil_contract").unwrap_err();
+    assert_eq!(err, ContractError::InvalidToken {});
+
+    let allow_token = ExecuteMsg::AllowToken {
+        token: Token::Cw20(Addr::unchecked("other_token")),
+        program_id: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("stranger", &[]),
+        allow_token.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        allow_token,
+    )
+    .unwrap();
+
+    let tokens: Vec<Token> = from_json(
+        query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::AllowedTokens { program_id: None },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Cw20(Addr::unchecked("vested_token")),
+            Token::Cw20(Addr::unchecked("other_token")),
+        ]
+    );
+
+    register_from(&mut deps, "other_token").unwrap();
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::DisallowToken {
+            token: Token::Cw20(Addr::unchecked("other_token")),
+            program_id: None,
+        },
+    )
+    .unwrap();
+    let err = register_from(&mut deps, "other_token").unwrap_err();
+    assert_eq!(err, ContractError::InvalidToken {});
+}
+
+#[test]
+fn claim_ownership_moves_default_program() {
+    let (mut deps, env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 1000)],
+            clawbackable: None,
+            mint_nft: None,
+        }],
+        1000,
+    );
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::CreateProgram {
+            owner: "owner".to_string(),
+            token: Token::Native("ustake".to_string()),
+            clawback_recipient: None,
+            clawbackable: None,
+            transfer_policy: None,
+            operator: None,
+        },
+    )
+    .unwrap();
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::ProposeNewOwner {
+            owner: "new_owner".to_string(),
+            expires_in: 100,
+        },
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("new_owner", &[]),
+        ExecuteMsg::ClaimOwnership {},
+    )
+    .unwrap();
+
+    let clawback = ExecuteMsg::Clawback {
+        recipient: Addr::unchecked("beneficiary"),
+        amount: None,
+        schedule_ids: None,
+        clawback_recipient: None,
+        token: None,
+        program_id: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        clawback.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("new_owner", &[]),
+        clawback,
+    )
+    .unwrap();
+
+    assert_eq!(
+        CONFIG.load(deps.as_ref().storage).unwrap().owner,
+        Addr::unchecked("new_owner")
+    );
+    // Other programs keep their owner
+    assert_eq!(
+        PROGRAMS.load(deps.as_ref().storage, 1).unwrap().owner,
+        Addr::unchecked("owner")
+    );
+}
+
+#[test]
+fn programs_are_owned_separately() {
+    let (mut deps, mut env) = setup();
+
+    let create_program = ExecuteMsg::CreateProgram {
+        owner: "advisors_owner".to_string(),
+        token: Token::Native("ustake".to_string()),
+        clawback_recipient: Some("advisors_treasury".to_string()),
+        clawbackable: Some(false),
+        transfer_policy: None,
+        operator: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("advisors_owner", &[]),
+        create_program.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        create_program,
+    )
+    .unwrap();
+
+    let register_msg = ExecuteMsg::RegisterVestingAccounts {
+        vesting_accounts: vec![
+            VestingAccount {
+                address: "advisor1".to_string(),
+                schedules: vec![linear_schedule(100, 200, 1000)],
+                clawbackable: None,
+                mint_nft: None,
+            },
+            VestingAccount {
+                address: "advisor2".to_string(),
+                schedules: vec![linear_schedule(100, 200, 1000)],
+                clawbackable: Some(true),
+                mint_nft: None,
+            },
+        ],
+        program_id: Some(1),
+    };
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &coins(2000, "ustake")),
+        register_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("advisors_owner", &coins(2000, "ustake")),
+        register_msg,
+    )
+    .unwrap();
+
+    env.block.time = Timestamp::from_seconds(150);
+    let clawback = |recipient: &str| ExecuteMsg::Clawback {
+        recipient: Addr::unchecked(recipient),
+        amount: None,
+        schedule_ids: None,
+        clawback_recipient: None,
+        token: None,
+        program_id: Some(1),
+    };
+
+    // The program default applies to the schedules without a flag
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("advisors_owner", &[]),
+        clawback("advisor1"),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        clawback("advisor2"),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("advisors_owner", &[]),
+        clawback("advisor2"),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(BankMsg::Send {
+                to_address: "advisor2".to_string(),
+                amount: coins(500, "ustake"),
+            }),
+            SubMsg::new(BankMsg::Send {
+                to_address: "advisors_treasury".to_string(),
+                amount: coins(500, "ustake"),
+            }),
+        ]
+    );
+
+    let res: ProgramsResponse = from_json(
+        query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Programs {
+                start_after: Some(0),
+                limit: None,
+                order_by: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.programs,
+        vec![ProgramResponse {
+            program_id: 1,
+            owner: Addr::unchecked("advisors_owner"),
+            token: Token::Native("ustake".to_string()),
+            clawback_recipient: Some(Addr::unchecked("advisors_treasury")),
+            clawbackable: false,
+            transfer_policy: TransferPolicy::Beneficiary,
+            operator: None,
+        }]
+    );
+
+    // Accounts of the program are not visible in the default program
+    let err = query(
+        deps.as_ref(),
+        env,
+        QueryMsg::VestingAccount {
+            address: "advisor1".to_string(),
+            token: Some(Token::Native("ustake".to_string())),
+            program_id: None,
+        },
+    )
+    .unwrap_err();
+    assert!(matches!(err, StdError::NotFound { .. }));
+}
+
+#[test]
+fn transfer_vesting_account() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![
+            VestingAccount {
+                address: "beneficiary".to_string(),
+                schedules: vec![linear_schedule(100, 200, 1000)],
+                clawbackable: None,
+                mint_nft: None,
+            },
+            VestingAccount {
+                address: "new_wallet".to_string(),
+                schedules: vec![linear_schedule(100, 300, 1000)],
+                clawbackable: None,
+                mint_nft: None,
+            },
+        ],
+        2000,
+    );
+
+    env.block.time = Timestamp::from_seconds(150);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        ExecuteMsg::Claim {
+            recipient: None,
+            splits: None,
+            amount: None,
+            token: None,
+            program_id: None,
+            msg: None,
+        },
+    )
+    .unwrap();
+
+    let transfer_msg = ExecuteMsg::TransferVestingAccount {
+        new_address: "new_wallet".to_string(),
+        token: None,
+        program_id: None,
+    };
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        transfer_msg.clone(),
+    )
+    .unwrap();
+
+    // The accounts are merged, the vested part of the moved schedule is released already
+    let account = query_vesting_account(&deps, &env, "new_wallet");
+    assert_eq!(account.info.schedules.len(), 2);
+    assert_eq!(account.info.released_amount, Uint128::new(500));
+    assert_eq!(
+        query_available_amount(&deps, &env, "new_wallet"),
+        Uint128::new(250)
+    );
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        transfer_msg,
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::UpdateProgram {
+            program_id: None,
+            owner: None,
+            clawback_recipient: None,
+            clawbackable: None,
+            transfer_policy: Some(TransferPolicy::OwnerApproval),
+            operator: None,
+        },
+    )
+    .unwrap();
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("new_wallet", &[]),
+        ExecuteMsg::TransferVestingAccount {
+            new_address: "newer_wallet".to_string(),
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    let pending_transfer: Option<Addr> = from_json(
+        query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::PendingTransfer {
+                address: "new_wallet".to_string(),
+                token: None,
+                program_id: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(pending_transfer, Some(Addr::unchecked("newer_wallet")));
+    assert_eq!(
+        query_vesting_account(&deps, &env, "new_wallet")
+            .info
+            .schedules
+            .len(),
+        2
+    );
+
+    let approve_msg = ExecuteMsg::ApproveVestingAccountTransfer {
+        address: "new_wallet".to_string(),
+        token: None,
+        program_id: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("new_wallet", &[]),
+        approve_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        approve_msg,
+    )
+    .unwrap();
+
+    let account = query_vesting_account(&deps, &env, "newer_wallet");
+    assert_eq!(account.info.schedules.len(), 2);
+    assert_eq!(account.info.released_amount, Uint128::new(500));
+    assert!(query(
+        deps.as_ref(),
+        env,
+        QueryMsg::VestingAccount {
+            address: "new_wallet".to_string(),
+            token: None,
+            program_id: None,
+        },
+    )
+    .is_err());
+}
+
+#[test]
+fn vesting_account_nft() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![
+            VestingAccount {
+                address: "beneficiary".to_string(),
+                schedules: vec![linear_schedule(100, 200, 1000)],
+                clawbackable: None,
+                mint_nft: Some(true),
+            },
+            VestingAccount {
+                address: "other".to_string(),
+                schedules: vec![linear_schedule(100, 200, 1000)],
+                clawbackable: None,
+                mint_nft: None,
+            },
+        ],
+        2000,
+    );
+
+    let owner_of: OwnerOfResponse = from_json(
+        query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::OwnerOf {
+                token_id: "0".to_string(),
+                include_expired: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(owner_of.owner, "beneficiary");
+
+    // Merging would leave the token representing two grants
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        ExecuteMsg::TransferNft {
+            recipient: "other".to_string(),
+            token_id: "0".to_string(),
+        },
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::Std(StdError::GenericErr { .. })
+    ));
+
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("other", &[]),
+        ExecuteMsg::TransferNft {
+            recipient: "other".to_string(),
+            token_id: "0".to_string(),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        ExecuteMsg::TransferNft {
+            recipient: "buyer".to_string(),
+            token_id: "0".to_string(),
+        },
+    )
+    .unwrap();
+
+    env.block.time = Timestamp::from_seconds(150);
+    let claim_msg = ExecuteMsg::Claim {
+        recipient: None,
+        splits: None,
+        amount: None,
+        token: None,
+        program_id: None,
+        msg: None,
+    };
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        claim_msg.clone(),
+    )
+    .unwrap_err();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("buyer", &[]),
+        claim_msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![cw20_transfer("buyer", 500)]);
+
+    let nft_info: NftInfoResponse = from_json(
+        query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::NftInfo {
+                token_id: "0".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(nft_info.extension.address, Addr::unchecked("buyer"));
+    assert_eq!(nft_info.extension.info.released_amount, Uint128::new(500));
+    assert_eq!(
+        nft_info.extension.info.schedules,
+        vec![VestingSchedule {
+            clawbackable: Some(true),
+            ..linear_schedule(100, 200, 1000)
+        }]
+    );
+
+    let tokens: TokensResponse = from_json(
+        query(
+            deps.as_ref(),
+            env,
+            QueryMsg::Tokens {
+                owner: "buyer".to_string(),
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(tokens.tokens, vec!["0".to_string()]);
+
+    // Approvals are not part of the interface
+    assert!(from_json::<ExecuteMsg>(
+        br#"{"approve":{"spender":"marketplace","token_id":"0","expires":null}}"#
+    )
+    .is_err());
+}
+
+#[test]
+fn claim_on_behalf_of_beneficiary() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 1000)],
+            clawbackable: None,
+            mint_nft: None,
+        }],
+        1000,
+    );
+
+    let claim_for = |amount: Option<u128>| ExecuteMsg::ClaimFor {
+        beneficiary: "beneficiary".to_string(),
+        amount: amount.map(Uint128::new),
+        token: None,
+        program_id: None,
+    };
+
+    env.block.time = Timestamp::from_seconds(150);
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("custodian", &[]),
+        claim_for(None),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let grant_claimer = ExecuteMsg::GrantClaimer {
+        claimer: "custodian".to_string(),
+        expires: Some(Expiration::AtTime(Timestamp::from_seconds(180))),
+        max_amount: Some(Uint128::new(600)),
+        recipient: Some("cold_wallet".to_string()),
+        token: None,
+        program_id: None,
+    };
+    // Addresses without a vesting account cannot grant claimers ahead of time
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("stranger", &[]),
+        grant_claimer.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        grant_claimer,
+    )
+    .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("custodian", &[]),
+        claim_for(Some(700)),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::AmountIsNotAvailable {});
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("custodian", &[]),
+        claim_for(None),
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![cw20_transfer("cold_wallet", 500)]);
+
+    // Everything available stops at the rest of the maximum amount
+    env.block.time = Timestamp::from_seconds(170);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("custodian", &[]),
+        claim_for(None),
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![cw20_transfer("cold_wallet", 100)]);
+    let claimer: Option<ClaimerInfo> = from_json(
+        query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Claimer {
+                beneficiary: "beneficiary".to_string(),
+                claimer: "custodian".to_string(),
+                token: None,
+                program_id: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(claimer.unwrap().max_amount, Some(Uint128::zero()));
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        ExecuteMsg::GrantClaimer {
+            claimer: "custodian".to_string(),
+            expires: Some(Expiration::AtTime(Timestamp::from_seconds(180))),
+            max_amount: None,
+            recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("custodian", &[]),
+        claim_for(Some(50)),
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![cw20_transfer("beneficiary", 50)]);
+
+    env.block.time = Timestamp::from_seconds(180);
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("custodian", &[]),
+        claim_for(None),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        ExecuteMsg::RevokeClaimer {
+            claimer: "custodian".to_string(),
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    let claimer: Option<ClaimerInfo> = from_json(
+        query(
+            deps.as_ref(),
+            env,
+            QueryMsg::Claimer {
+                beneficiary: "beneficiary".to_string(),
+                claimer: "custodian".to_string(),
+                token: None,
+                program_id: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(claimer, None);
+}
+
+#[test]
+fn claim_split_between_recipients() {
+    let (mut deps, env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(0, 100, 1000)],
+            clawbackable: None,
+            mint_nft: None,
+        }],
+        1000,
+    );
+
+    let claim = |recipient: Option<&str>, splits: Vec<(&str, &str)>| ExecuteMsg::Claim {
+        recipient: recipient.map(|recipient| recipient.to_string()),
+        splits: Some(
+            splits
+                .into_iter()
+                .map(|(recipient, share)| (recipient.to_string(), share.parse().unwrap()))
+                .collect(),
+        ),
+        amount: Some(Uint128::new(101)),
+        token: None,
+        program_id: None,
+        msg: None,
+    };
+
+    for invalid_claim in [
+        claim(None, vec![("tax_wallet", "0.3"), ("wallet", "0.6")]),
+        claim(None, vec![("tax_wallet", "0.3"), ("wallet", "0.8")]),
+        claim(None, vec![]),
+        claim(Some("wallet"), vec![("tax_wallet", "1")]),
+    ] {
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("beneficiary", &[]),
+            invalid_claim,
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::ClaimSplitError {});
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        claim(None, vec![("tax_wallet", "0.3"), ("wallet", "0.7")]),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![cw20_transfer("tax_wallet", 30), cw20_transfer("wallet", 71)]
+    );
+    assert_eq!(
+        query_vesting_account(&deps, &env, "beneficiary")
+            .info
+            .released_amount,
+        Uint128::new(101)
+    );
+}
+
+#[test]
+fn claim_to_default_recipient() {
+    let (mut deps, env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(0, 100, 1000)],
+            clawbackable: None,
+            mint_nft: None,
+        }],
+        1000,
+    );
+
+    let set_default_recipient = |recipient: Option<&str>| ExecuteMsg::SetDefaultRecipient {
+        recipient: recipient.map(|recipient| recipient.to_string()),
+        token: None,
+        program_id: None,
+    };
+    let claim = |recipient: Option<&str>| ExecuteMsg::Claim {
+        recipient: recipient.map(|recipient| recipient.to_string()),
+        splits: None,
+        amount: Some(Uint128::new(100)),
+        token: None,
+        program_id: None,
+        msg: None,
+    };
+
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("stranger", &[]),
+        set_default_recipient(Some("hardware_wallet")),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        set_default_recipient(Some("hardware_wallet")),
+    )
+    .unwrap();
+    assert_eq!(
+        query_vesting_account(&deps, &env, "beneficiary").default_recipient,
+        Some(Addr::unchecked("hardware_wallet"))
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        claim(None),
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![cw20_transfer("hardware_wallet", 100)]);
+
+    // An explicit recipient still takes precedence
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        claim(Some("exchange")),
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![cw20_transfer("exchange", 100)]);
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Distribute {
+            addresses: vec!["beneficiary".to_string()],
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![cw20_transfer("hardware_wallet", 800)]);
+
+    // The vested part of a clawback is paid to the default recipient as well
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(50, 150, 1000)],
+            clawbackable: None,
+            mint_nft: None,
+        }],
+        1000,
+    );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary"),
+            amount: None,
+            schedule_ids: None,
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            cw20_transfer("hardware_wallet", 500),
+            cw20_transfer("owner", 500),
+        ]
+    );
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        set_default_recipient(None),
+    )
+    .unwrap();
+    assert_eq!(
+        query_vesting_account(&deps, &env, "beneficiary").default_recipient,
+        None
+    );
+}
+
+#[test]
+fn claim_and_send_to_contract() {
+    let (mut deps, env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(0, 100, 1000)],
+            clawbackable: None,
+            mint_nft: None,
+        }],
+        1000,
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        ExecuteMsg::Claim {
+            recipient: Some("staking".to_string()),
+            splits: None,
+            amount: Some(Uint128::new(400)),
+            token: None,
+            program_id: None,
+            msg: Some(to_json_binary("stake").unwrap()),
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(WasmMsg::Execute {
+            contract_addr: "vested_token".to_string(),
+            funds: vec![],
+            msg: to_json_binary(&Cw20ExecuteMsg::Send {
+                contract: "staking".to_string(),
+                amount: Uint128::new(400),
+                msg: to_json_binary("stake").unwrap(),
+            })
+            .unwrap(),
+        })]
+    );
+    assert_eq!(
+        query_available_amount(&deps, &env, "beneficiary"),
+        Uint128::new(600)
+    );
+}
+
+#[test]
+fn distribute_vested_tokens() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        ["beneficiary1", "beneficiary2", "beneficiary3"]
+            .iter()
+            .map(|address| VestingAccount {
+                address: address.to_string(),
+                schedules: vec![linear_schedule(100, 200, 1000)],
+                clawbackable: None,
+                mint_nft: None,
+            })
+            .collect(),
+        3000,
+    );
+
+    env.block.time = Timestamp::from_seconds(150);
+
+    let distribute = ExecuteMsg::Distribute {
+        addresses: vec![
+            "beneficiary1".to_string(),
+            "nobody".to_string(),
+            "beneficiary2".to_string(),
+        ],
+        token: None,
+        program_id: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("operator", &[]),
+        distribute.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::UpdateProgram {
+            program_id: None,
+            owner: None,
+            clawback_recipient: None,
+            clawbackable: None,
+            transfer_policy: None,
+            operator: Some(AddrUpdate::Set("operator".to_string())),
+        },
+    )
+    .unwrap();
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary1", &[]),
+        ExecuteMsg::Claim {
+            recipient: None,
+            splits: None,
+            amount: Some(Uint128::new(200)),
+            token: None,
+            program_id: None,
+            msg: None,
+        },
+    )
+    .unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("operator", &[]),
+        distribute,
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            cw20_transfer("beneficiary1", 300),
+            cw20_transfer("beneficiary2", 500),
+        ]
+    );
+    assert_eq!(
+        res.attributes[3..],
+        vec![
+            attr("address", "beneficiary1"),
+            attr("claimed_amount", "300"),
+            attr("skipped", "nobody"),
+            attr("address", "beneficiary2"),
+            attr("claimed_amount", "500"),
+        ]
+    );
+    assert_eq!(
+        query_vesting_account(&deps, &env, "beneficiary2")
+            .info
+            .released_amount,
+        Uint128::new(500)
+    );
+
+    env.block.time = Timestamp::from_seconds(200);
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::DistributeAll {
+            start_after: None,
+            limit: Some(2),
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            cw20_transfer("beneficiary1", 500),
+            cw20_transfer("beneficiary2", 500),
+        ]
+    );
+    assert_eq!(
+        res.attributes.last(),
+        Some(&attr("last_address", "beneficiary2"))
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("operator", &[]),
+        ExecuteMsg::DistributeAll {
+            start_after: Some("beneficiary2".to_string()),
+            limit: Some(2),
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![cw20_transfer("beneficiary3", 1000)]);
+    assert_eq!(
+        query_available_amount(&deps, &env, "beneficiary3"),
+        Uint128::zero()
+    );
+}
+
+#[test]
+fn voting_power_at_height() {
+    let (mut deps, mut env) = setup();
+    let start_height = env.block.height;
+
+    register(
+        &mut deps,
+        &env,
+        vec![
+            VestingAccount {
+                address: "beneficiary1".to_string(),
+                schedules: vec![linear_schedule(100, 200, 1000)],
+                clawbackable: None,
+                mint_nft: None,
+            },
+            VestingAccount {
+                address: "beneficiary2".to_string(),
+                schedules: vec![linear_schedule(100, 200, 1000)],
+                clawbackable: None,
+                mint_nft: None,
+            },
+        ],
+        2000,
+    );
+
+    env.block.height = start_height + 1;
+    env.block.time = Timestamp::from_seconds(150);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary1", &[]),
+        ExecuteMsg::Claim {
+            recipient: None,
+            splits: None,
+            amount: None,
+            token: None,
+            program_id: None,
+            msg: None,
+        },
+    )
+    .unwrap();
+
+    env.block.height = start_height + 2;
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary2"),
+            amount: None,
+            schedule_ids: None,
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+
+    env.block.height = start_height + 3;
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary1", &[]),
+        ExecuteMsg::TransferVestingAccount {
+            new_address: "new_wallet".to_string(),
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+
+    let voting_power = |address: &str, height: u64| -> u128 {
+        let res: VotingPowerAtHeightResponse = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::VotingPowerAtHeight {
+                    address: address.to_string(),
+                    height: Some(height),
+                    token: None,
+                    program_id: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(res.height, height);
+        res.power.u128()
+    };
+    let total_power = |height: Option<u64>| -> u128 {
+        let res: TotalPowerAtHeightResponse = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::TotalPowerAtHeight {
+                    height,
+                    token: None,
+                    program_id: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        res.power.u128()
+    };
+
+    // Heights are read at the start of the block
+    assert_eq!(voting_power("beneficiary1", start_height), 0);
+    assert_eq!(total_power(Some(start_height)), 0);
+
+    assert_eq!(voting_power("beneficiary1", start_height + 1), 1000);
+    assert_eq!(voting_power("beneficiary2", start_height + 1), 1000);
+    assert_eq!(total_power(Some(start_height + 1)), 2000);
+
+    assert_eq!(voting_power("beneficiary1", start_height + 2), 500);
+    assert_eq!(total_power(Some(start_height + 2)), 1500);
+
+    assert_eq!(voting_power("beneficiary2", start_height + 3), 0);
+    assert_eq!(total_power(Some(start_height + 3)), 500);
+
+    assert_eq!(voting_power("beneficiary1", start_height + 4), 0);
+    assert_eq!(voting_power("new_wallet", start_height + 4), 500);
+    assert_eq!(total_power(Some(start_height + 4)), 500);
+    assert_eq!(total_power(None), 500);
+}
+
+#[test]
+fn voting_power_adds_up_programs() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 1000)],
+            clawbackable: None,
+            mint_nft: None,
+        }],
+        1000,
+    );
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::CreateProgram {
+            owner: "program_owner".to_string(),
+            token: Token::Cw20(Addr::unchecked("vested_token")),
+            clawback_recipient: None,
+            clawbackable: None,
+            transfer_policy: None,
+            operator: None,
+        },
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("vested_token", &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "program_owner".to_string(),
+            amount: Uint128::new(500),
+            msg: to_json_binary(&Cw20HookMsg::RegisterVestingAccounts {
+                vesting_accounts: vec![VestingAccount {
+                    address: "beneficiary".to_string(),
+                    schedules: vec![linear_schedule(100, 200, 500)],
+                    clawbackable: None,
+                    mint_nft: None,
+                }],
+                program_id: Some(1),
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap();
+
+    env.block.height += 1;
+    let voting_power = |program_id: Option<u64>| -> Uint128 {
+        let res: VotingPowerAtHeightResponse = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::VotingPowerAtHeight {
+                    address: "beneficiary".to_string(),
+                    height: None,
+                    token: None,
+                    program_id,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        res.power
+    };
+    let total_power = |program_id: Option<u64>| -> Uint128 {
+        let res: TotalPowerAtHeightResponse = from_json(
+            query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::TotalPowerAtHeight {
+                    height: None,
+                    token: None,
+                    program_id,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        res.power
+    };
+
+    assert_eq!(voting_power(Some(0)), Uint128::new(1000));
+    assert_eq!(voting_power(Some(1)), Uint128::new(500));
+    assert_eq!(voting_power(None), Uint128::new(1500));
+    assert_eq!(total_power(Some(1)), Uint128::new(500));
+    assert_eq!(total_power(None), Uint128::new(1500));
+}
+
+#[test]
+fn vesting_amounts_at_height() {
+    let (mut deps, mut env) = setup();
+    let start_height = env.block.height;
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 1000)],
+            clawbackable: None,
+            mint_nft: None,
+        }],
+        1000,
+    );
+
+    env.block.height = start_height + 1;
+    env.block.time = Timestamp::from_seconds(150);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        ExecuteMsg::Claim {
+            recipient: None,
+            splits: None,
+            amount: Some(Uint128::new(300)),
+            token: None,
+            program_id: None,
+            msg: None,
+        },
+    )
+    .unwrap();
+
+    let amount_at = |msg: QueryMsg| -> Uint128 {
+        from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap()
+    };
+    let released_at = |height: u64| QueryMsg::ReleasedAt {
+        address: "beneficiary".to_string(),
+        height,
+        token: None,
+        program_id: None,
+    };
+    let unreleased_at = |height: u64| QueryMsg::UnreleasedAt {
+        address: "beneficiary".to_string(),
+        height,
+        token: None,
+        program_id: None,
+    };
+    let total_unreleased_at = |height: u64| QueryMsg::TotalUnreleasedAt {
+        height,
+        token: None,
+        program_id: None,
+    };
+
+    assert_eq!(amount_at(unreleased_at(start_height)), Uint128::zero());
+    assert_eq!(
+        amount_at(unreleased_at(start_height + 1)),
+        Uint128::new(1000)
+    );
+    assert_eq!(amount_at(released_at(start_height + 1)), Uint128::zero());
+    assert_eq!(
+        amount_at(unreleased_at(start_height + 2)),
+        Uint128::new(700)
+    );
+    assert_eq!(amount_at(released_at(start_height + 2)), Uint128::new(300));
+    assert_eq!(
+        amount_at(total_unreleased_at(start_height + 1)),
+        Uint128::new(1000)
+    );
+    assert_eq!(
+        amount_at(total_unreleased_at(start_height + 2)),
+        Uint128::new(700)
+    );
+}
+
+#[test]
+fn totals_track_deposits_releases_and_clawbacks() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![
+            VestingAccount {
+                address: "beneficiary1".to_string(),
+                schedules: vec![linear_schedule(100, 200, 1000)],
+                clawbackable: None,
+                mint_nft: None,
+            },
+            VestingAccount {
+                address: "beneficiary2".to_string(),
+                schedules: vec![linear_schedule(100, 200, 1000)],
+                clawbackable: None,
+                mint_nft: None,
+            },
+        ],
+        2000,
+    );
+
+    env.block.time = Timestamp::from_seconds(150);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary1", &[]),
+        ExecuteMsg::Claim {
+            recipient: None,
+            splits: None,
+            amount: Some(Uint128::new(200)),
+            token: None,
+            program_id: None,
+            msg: None,
+        },
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::Clawback {
+            recipient: Addr::unchecked("beneficiary2"),
+            amount: Some(Uint128::new(300)),
+            schedule_ids: None,
+            clawback_recipient: None,
+            token: None,
+            program_id: None,
+        },
+    )
+    .unwrap();
+
+    let totals: TotalsResponse = from_json(
+        query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Totals {
+                token: None,
+                program_id: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        totals,
+        TotalsResponse {
+            program_id: 0,
+            token: Token::Cw20(Addr::unchecked("vested_token")),
+            deposited: Uint128::new(2000),
+            released: Uint128::new(700),
+            clawed_back: Uint128::new(300),
+            outstanding: Uint128::new(1000),
+        }
+    );
+
+    // Other tokens have their own totals
+    let totals: TotalsResponse = from_json(
+        query(
+            deps.as_ref(),
+            env,
+            QueryMsg::Totals {
+                token: Some(Token::Native("ustake".to_string())),
+                program_id: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(totals.deposited, Uint128::zero());
+}
+
+#[test]
+fn invariants_report_surplus_and_deficit() {
+    let (mut deps, mut env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 1000)],
+            clawbackable: None,
+            mint_nft: None,
+        }],
+        1000,
+    );
+    mock_cw20_balance(&mut deps, 1000);
+    assert_eq!(
+        query_invariants(&deps, &env, None),
+        InvariantsResponse {
+            token: Token::Cw20(Addr::unchecked("vested_token")),
+            balance: Uint128::new(1000),
+            outstanding: Uint128::new(1000),
+            tracked_outstanding: Uint128::new(1000),
+            totals_mismatch: false,
+            surplus: Uint128::zero(),
+            deficit: Uint128::zero(),
+        }
+    );
+
+    // The claim transfer is not executed by the mock, so the tokens stay in the contract
+    env.block.time = Timestamp::from_seconds(150);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        ExecuteMsg::Claim {
+            recipient: None,
+            splits: None,
+            amount: Some(Uint128::new(200)),
+            token: None,
+            program_id: None,
+            msg: None,
+        },
+    )
+    .unwrap();
+    let res = query_invariants(&deps, &env, None);
+    assert_eq!(res.outstanding, Uint128::new(800));
+    assert_eq!(res.surplus, Uint128::new(200));
+    assert_eq!(res.deficit, Uint128::zero());
+
+    // Tokens leaving the contract outside of the vesting break the invariant
+    mock_cw20_balance(&mut deps, 500);
+    let res = query_invariants(&deps, &env, None);
+    assert_eq!(res.surplus, Uint128::zero());
+    assert_eq!(res.deficit, Uint128::new(300));
+
+    // Native obligations add up across programs
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::CreateProgram {
+            owner: "owner".to_string(),
+            token: Token::Native("ustake".to_string()),
+            clawback_recipient: None,
+            clawbackable: None,
+            transfer_policy: None,
+            operator: None,
+        },
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::AllowToken {
+            token: Token::Native("ustake".to_string()),
+            program_id: None,
+        },
+    )
+    .unwrap();
+    for program_id in [0, 1] {
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("owner", &coins(1000, "ustake")),
+            ExecuteMsg::RegisterVestingAccounts {
+                vesting_accounts: vec![VestingAccount {
+                    address: "beneficiary".to_string(),
+                    schedules: vec![linear_schedule(100, 200, 1000)],
+                    clawbackable: None,
+                    mint_nft: None,
+                }],
+                program_id: Some(program_id),
+            },
+        )
+        .unwrap();
+    }
+
+    let ustake = Some(Token::Native("ustake".to_string()));
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, coins(2000, "ustake"));
+    let res = query_invariants(&deps, &env, ustake.clone());
+    assert_eq!(res.outstanding, Uint128::new(2000));
+    assert_eq!(res.deficit, Uint128::zero());
+
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, coins(1999, "ustake"));
+    let res = query_invariants(&deps, &env, ustake);
+    assert_eq!(res.surplus, Uint128::zero());
+    assert_eq!(res.deficit, Uint128::new(1));
+}
+
+#[test]
+fn invariants_flag_totals_out_of_sync_with_accounts() {
+    let (mut deps, env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 1000)],
+            clawbackable: None,
+            mint_nft: None,
+        }],
+        1000,
+    );
+    mock_cw20_balance(&mut deps, 1000);
+
+    // Counters drifting from the accounts do not hide the amount owed to the beneficiaries
+    TOTALS
+        .save(
+            deps.as_mut().storage,
+            ("vested_token", 0),
+            &Totals {
+                deposited: Uint128::new(1000),
+                released: Uint128::new(600),
+                clawed_back: Uint128::zero(),
+            },
+        )
+        .unwrap();
+    let res = query_invariants(&deps, &env, None);
+    assert_eq!(res.outstanding, Uint128::new(1000));
+    assert_eq!(res.tracked_outstanding, Uint128::new(400));
+    assert!(res.totals_mismatch);
+    assert_eq!(res.surplus, Uint128::zero());
+
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        ExecuteMsg::WithdrawSurplus {
+            recipient: "treasury".to_string(),
+            amount: Uint128::new(600),
+            token: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::AmountIsNotAvailable {});
+
+    // Counters above the accounts keep the larger amount in the contract as well
+    TOTALS
+        .save(
+            deps.as_mut().storage,
+            ("vested_token", 0),
+            &Totals {
+                deposited: Uint128::new(1200),
+                released: Uint128::zero(),
+                clawed_back: Uint128::zero(),
+            },
+        )
+        .unwrap();
+    mock_cw20_balance(&mut deps, 1300);
+    let res = query_invariants(&deps, &env, None);
+    assert_eq!(res.surplus, Uint128::new(300));
+    assert!(res.totals_mismatch);
+
+    let withdraw = |deps: &mut MockDeps, amount: u128| {
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("owner", &[]),
+            ExecuteMsg::WithdrawSurplus {
+                recipient: "treasury".to_string(),
+                amount: Uint128::new(amount),
+                token: None,
+            },
+        )
+    };
+    assert_eq!(
+        withdraw(&mut deps, 101).unwrap_err(),
+        ContractError::AmountIsNotAvailable {}
+    );
+    assert_eq!(
+        withdraw(&mut deps, 100).unwrap().messages,
+        vec![cw20_transfer("treasury", 100)]
+    );
+    assert_eq!(
+        withdraw(&mut deps, 0).unwrap_err(),
+        ContractError::ZeroAmount {}
+    );
+}
+
+#[test]
+fn withdraw_surplus() {
+    let (mut deps, env) = setup();
+
+    register(
+        &mut deps,
+        &env,
+        vec![VestingAccount {
+            address: "beneficiary".to_string(),
+            schedules: vec![linear_schedule(100, 200, 1000)],
+            clawbackable: None,
+            mint_nft: None,
+        }],
+        1000,
+    );
+    // Tokens sent to the contract without registering vesting accounts
+    mock_cw20_balance(&mut deps, 1500);
+
+    let withdraw_surplus = |amount: u128| ExecuteMsg::WithdrawSurplus {
+        recipient: "treasury".to_string(),
+        amount: Uint128::new(amount),
+        token: None,
+    };
+
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("beneficiary", &[]),
+        withdraw_surplus(500),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    // The grants cannot be touched
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        withdraw_surplus(501),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::AmountIsNotAvailable {});
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("owner", &[]),
+        withdraw_surplus(500),
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![cw20_transfer("treasury", 500)]);
+
+    // No surplus is left once the contract holds less than it owes
+    mock_cw20_balance(&mut deps, 900);
+    let err = execute(
+        deps.as_mut(),
+        env,
+        mock_info("owner", &[]),
+        withdraw_surplus(1),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::AmountIsNotAvailable {});
+}
+
+#[test]
+fn migrate_terminates_clawed_back_accounts() {
+    // A legacy deployment only holds the config and the vesting infos. The legacy clawback left
+    // a copy of the account under the owner's key.
+    let legacy_deps = || {
+        let mut deps = mock_dependencies();
+        CONFIG_V100
+            .save(
+                deps.as_mut().storage,
+                &ConfigV100 {
+                    owner: Addr::unchecked("owner"),
+                    token_addr: Addr::unchecked("vested_token"),
+                    clawback_recipient: None,
+                },
+            )
+            .unwrap();
+
+        let legacy_info = VestingInfoV100 {
+            schedules: vec![
+                linear_schedule(100, 200, 1000),
+                linear_schedule(300, 400, 1000),
+            ],
+            released_amount: Uint128::new(100),
+            clawbackable: Some(true),
+        };
+        VESTING_INFO_V100
+            .save(
+                deps.as_mut().storage,
+                &Addr::unchecked("beneficiary"),
+                &legacy_info,
+            )
+            .unwrap();
+        VESTING_INFO_V100
+            .save(
+                deps.as_mut().storage,
+                &Addr::unchecked("owner"),
+                &VestingInfoV100 {
+                    released_amount: Uint128::new(1000),
+                    ..legacy_info
+                },
+            )
+            .unwrap();
+        deps
+    };
+    let mut deps = legacy_deps();
+    let mut env = mock_env();
+
+    migrate(
+        deps.as_mut(),
+        env.clone(),
+        MigrateMsg {
+            clawed_back_accounts: Some(vec![ClawedBackAccount {
+                address: "beneficiary".to_string(),
+                clawed_back_at: 150,
+            }]),
+        },
+    )
+    .unwrap();
+
+    let info = VESTING_INFO
+        .load(
+            deps.as_ref().storage,
+            (0, "vested_token", &Addr::unchecked("beneficiary")),
+        )
+        .unwrap();
+    assert_eq!(info.schedules[0].terminated_at, Some(150));
+    assert_eq!(info.schedules[1].terminated_at, None);
+    assert_eq!(info.schedules[1].clawbackable, Some(true));
+    assert_eq!(info.released_amount, Uint128::new(500));
+    assert!(!VESTING_INFO.has(
+        deps.as_ref().storage,
+        (0, "vested_token", &Addr::unchecked("owner"))
+    ));
+    assert_eq!(
+        UNRELEASED_AMOUNTS
+            .may_load_at_height(
+                deps.as_ref().storage,
+                (0, "vested_token", &Addr::unchecked("beneficiary")),
+                env.block.height + 1,
+            )
+            .unwrap(),
+        Some(Uint128::new(1000))
+    );
+    assert!(VESTING_INFO_V100
+        .may_load(deps.as_ref().storage, &Addr::unchecked("beneficiary"))
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        PROGRAMS.load(deps.as_ref().storage, 0).unwrap().token,
+        Token::Cw20(Addr::unchecked("vested_token"))
+    );
+    assert_eq!(
+        CONFIG.load(deps.as_ref().storage).unwrap().owner,
+        Addr::unchecked("owner")
+    );
+    assert_eq!(
+        TOTALS
+            .load(deps.as_ref().storage, ("vested_token", 0))
+            .unwrap(),
+        Totals {
+            deposited: Uint128::new(2000),
+            released: Uint128::new(100),
+            clawed_back: Uint128::new(900),
+        }
+    );
+
+    // The migrated account keeps vesting its schedule that had not started
+    env.block.time = Timestamp::from_seconds(350);
+    assert_eq!(
+        query_available_amount(&deps, &env, "beneficiary"),
+        Uint128::new(500)
+    );
+
+    // The owner's copy is dropped even when the account is not listed
+    let mut deps = legacy_deps();
+    migrate(
+        deps.as_mut(),
+        env.clone(),
+        MigrateMsg {
+            clawed_back_accounts: None,
+        },
+    )
+    .unwrap();
+    assert!(!VESTING_INFO.has(
+        deps.as_ref().storage,
+        (0, "vested_token", &Addr::unchecked("owner"))
+    ));
+    assert!(VESTING_INFO.has(
+        deps.as_ref().storage,
+        (0, "vested_token", &Addr::unchecked("beneficiary"))
+    ));
+    assert_eq!(
+        TOTALS
+            .load(deps.as_ref().storage, ("vested_token", 0))
+            .unwrap()
+            .deposited,
+        Uint128::new(2000)
+    );
 }
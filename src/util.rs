@@ -1,5 +1,7 @@
 use cosmwasm_std::{Addr, Api, StdError, StdResult};
 
+use crate::msg::{AddrUpdate, Token};
+
 pub fn addr_validate_to_lower(api: &dyn Api, addr: impl Into<String>) -> StdResult<Addr> {
     let addr = addr.into();
     if addr.to_lowercase() != addr {
@@ -16,3 +18,21 @@ pub fn addr_opt_validate(api: &dyn Api, addr: &Option<String>) -> StdResult<Opti
         .map(|addr| addr_validate_to_lower(api, addr))
         .transpose()
 }
+
+pub fn addr_update(api: &dyn Api, update: AddrUpdate) -> StdResult<Option<Addr>> {
+    match update {
+        AddrUpdate::Set(addr) => Ok(Some(addr_validate_to_lower(api, addr)?)),
+        AddrUpdate::Clear {} => Ok(None),
+    }
+}
+
+pub fn token_validate(api: &dyn Api, token: Token) -> StdResult<Token> {
+    match token {
+        Token::Cw20(contract_addr) => Ok(Token::Cw20(addr_validate_to_lower(api, contract_addr)?)),
+        Token::Native(denom) => Ok(Token::Native(denom)),
+    }
+}
+
+pub fn token_opt_validate(api: &dyn Api, token: Option<Token>) -> StdResult<Option<Token>> {
+    token.map(|token| token_validate(api, token)).transpose()
+}